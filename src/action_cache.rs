@@ -0,0 +1,290 @@
+//! Content-addressed cache of action results.
+//!
+//! Each action is identified by a key hashed from everything that can affect its result. The
+//! entry for a key lists the files the action produced, whose contents are stored separately by
+//! hash so identical outputs are only stored once.
+
+use crate::cache_backend::CacheBackend;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+pub struct ActionCache {
+    backend: Box<dyn CacheBackend>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct Entry {
+    #[serde(default)]
+    outputs: Vec<Output>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct Output {
+    path: PathBuf,
+    hash: String,
+    executable: bool,
+}
+
+/// Key of performing `action` on inputs that hash to `input_hash`, along with `action_inputs`
+/// that only affect this action.
+pub fn action_key(
+    input_hash: &str,
+    action: impl std::fmt::Display,
+    action_inputs: &[String],
+) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(input_hash.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(action.to_string().as_bytes());
+    for input in action_inputs {
+        hasher.update(b"\0");
+        hasher.update(input.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Hash of the file at `path`, which is streamed since outputs like image tarballs can be large.
+fn hash_file(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    let mut file = std::fs::File::open(path).context(format!("Reading {path:?}"))?;
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+impl ActionCache {
+    pub fn new(backend: Box<dyn CacheBackend>) -> Self {
+        ActionCache { backend }
+    }
+
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<Entry>> {
+        match self.backend.read(&format!("ac/{key}"))? {
+            None => Ok(None),
+            Some(bytes) => {
+                let entry = toml::from_slice(&bytes).context(format!("Parsing entry {key}"))?;
+                Ok(Some(entry))
+            }
+        }
+    }
+
+    pub fn put(&self, key: &str, entry: &Entry) -> anyhow::Result<()> {
+        self.backend
+            .write(&format!("ac/{key}"), toml::to_string(entry)?.as_bytes())
+    }
+
+    /// Store every file in `dir` and return an entry listing them.
+    pub fn store_outputs(&self, dir: &Path) -> anyhow::Result<Entry> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut outputs = Vec::new();
+        for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let hash = hash_file(entry.path())?;
+            self.backend
+                .write_from_file(&format!("cas/{hash}"), entry.path())?;
+
+            outputs.push(Output {
+                path: entry.path().strip_prefix(dir)?.to_path_buf(),
+                hash,
+                executable: entry.metadata()?.permissions().mode() & 0o100 != 0,
+            });
+        }
+
+        Ok(Entry { outputs })
+    }
+
+    /// Write the outputs listed in `entry` into `dir`.
+    ///
+    /// Returns `false` when an output is missing from the cache, like when a remote cache evicted
+    /// it but kept the entry, or doesn't match its hash, so the action has to be performed again.
+    /// Entries with outputs outside of `dir` or malformed hashes are also misses, since anyone who
+    /// can write to a remote cache could otherwise write anywhere.
+    pub fn restore_outputs(&self, entry: &Entry, dir: &Path) -> anyhow::Result<bool> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let is_valid = |output: &Output| {
+            output.path.components().next().is_some()
+                && output
+                    .path
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_)))
+                && !output.hash.is_empty()
+                && output.hash.chars().all(|c| c.is_ascii_hexdigit())
+        };
+        if let Some(output) = entry.outputs.iter().find(|o| !is_valid(o)) {
+            eprintln!(
+                "Warning: ignoring cache entry with output {:?} hashed {:?}",
+                output.path, output.hash
+            );
+            return Ok(false);
+        }
+
+        for output in &entry.outputs {
+            let path = dir.join(&output.path);
+            std::fs::create_dir_all(path.parent().expect("joined onto dir"))?;
+            if !self
+                .backend
+                .read_to_file(&format!("cas/{}", output.hash), &path)?
+            {
+                return Ok(false);
+            }
+            if hash_file(&path)? != output.hash {
+                eprintln!("Warning: ignoring corrupt cache output {:?}", output.path);
+                return Ok(false);
+            }
+
+            if output.executable {
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_backend::LocalBackend;
+
+    fn cache(root: &tempfile::TempDir) -> ActionCache {
+        ActionCache::new(Box::new(LocalBackend::new(root.path())))
+    }
+
+    #[test]
+    fn missing_entry() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = cache(&root);
+
+        assert_eq!(cache.get("key").unwrap(), None);
+    }
+
+    #[test]
+    fn stores_empty_entry() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = cache(&root);
+
+        cache.put("key", &Entry::default()).unwrap();
+
+        assert_eq!(cache.get("key").unwrap(), Some(Entry::default()));
+    }
+
+    #[test]
+    fn restores_outputs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let cache = cache(&root);
+
+        let out = tempfile::tempdir().unwrap();
+        std::fs::create_dir(out.path().join("bin")).unwrap();
+        std::fs::write(out.path().join("bin/tool"), "binary").unwrap();
+        std::fs::set_permissions(
+            out.path().join("bin/tool"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        std::fs::write(out.path().join("data.txt"), "data").unwrap();
+
+        let entry = cache.store_outputs(out.path()).unwrap();
+        cache.put("key", &entry).unwrap();
+
+        let restored = tempfile::tempdir().unwrap();
+        let entry = cache.get("key").unwrap().unwrap();
+        cache.restore_outputs(&entry, restored.path()).unwrap();
+
+        let tool = restored.path().join("bin/tool");
+        assert_eq!(std::fs::read_to_string(&tool).unwrap(), "binary");
+        assert_ne!(tool.metadata().unwrap().permissions().mode() & 0o100, 0);
+        assert_eq!(
+            std::fs::read_to_string(restored.path().join("data.txt")).unwrap(),
+            "data"
+        );
+    }
+
+    #[test]
+    fn missing_output_is_not_restored() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = cache(&root);
+
+        let out = tempfile::tempdir().unwrap();
+        std::fs::write(out.path().join("data.txt"), "data").unwrap();
+        let entry = cache.store_outputs(out.path()).unwrap();
+        std::fs::remove_dir_all(root.path().join("cas")).unwrap();
+
+        let restored = tempfile::tempdir().unwrap();
+        assert!(!cache.restore_outputs(&entry, restored.path()).unwrap());
+    }
+
+    #[test]
+    fn outputs_outside_dir_are_not_restored() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = cache(&root);
+
+        let out = tempfile::tempdir().unwrap();
+        std::fs::write(out.path().join("data.txt"), "data").unwrap();
+        let hash = cache.store_outputs(out.path()).unwrap().outputs[0]
+            .hash
+            .clone();
+
+        let restored = tempfile::tempdir().unwrap();
+        for path in ["../escaped.txt", "/tmp/escaped.txt", "./data.txt", ""] {
+            let poisoned = Entry {
+                outputs: vec![Output {
+                    path: PathBuf::from(path),
+                    hash: hash.clone(),
+                    executable: false,
+                }],
+            };
+            assert!(!cache
+                .restore_outputs(&poisoned, &restored.path().join("out"))
+                .unwrap());
+        }
+        assert!(!restored.path().join("escaped.txt").exists());
+
+        let poisoned = Entry {
+            outputs: vec![Output {
+                path: PathBuf::from("data.txt"),
+                hash: String::from("../../escaped.txt"),
+                executable: false,
+            }],
+        };
+        assert!(!cache.restore_outputs(&poisoned, restored.path()).unwrap());
+    }
+
+    #[test]
+    fn corrupt_output_is_not_restored() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = cache(&root);
+
+        let out = tempfile::tempdir().unwrap();
+        std::fs::write(out.path().join("data.txt"), "data").unwrap();
+        let entry = cache.store_outputs(out.path()).unwrap();
+        let blob = root.path().join("cas").join(&entry.outputs[0].hash);
+        std::fs::write(blob, "truncated").unwrap();
+
+        let restored = tempfile::tempdir().unwrap();
+        assert!(!cache.restore_outputs(&entry, restored.path()).unwrap());
+    }
+
+    #[test]
+    fn key_depends_on_action() {
+        assert_ne!(
+            action_key("hash", "test", &[]),
+            action_key("hash", "lint", &[])
+        );
+    }
+
+    #[test]
+    fn key_depends_on_action_inputs() {
+        let input = [String::from("input")];
+        assert_ne!(
+            action_key("hash", "lint", &[]),
+            action_key("hash", "lint", &input)
+        );
+    }
+}
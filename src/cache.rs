@@ -32,7 +32,7 @@ pub fn load(from: PathBuf) -> anyhow::Result<()> {
         .filter_map(into_file_path);
 
     for path in absolute {
-        let to = PathBuf::from("/").join(&path.strip_prefix(&absolute_dir).unwrap());
+        let to = PathBuf::from("/").join(path.strip_prefix(&absolute_dir).unwrap());
         move_file(path, to)?;
     }
 
@@ -47,13 +47,13 @@ fn into_file_path(entry: walkdir::DirEntry) -> Option<PathBuf> {
     }
 }
 
-pub fn save(to: PathBuf) -> anyhow::Result<()> {
-    let cache_paths = crate::targets::targets()?
+pub fn save(config: &crate::Config, to: PathBuf) -> anyhow::Result<()> {
+    let cache_paths = crate::targets::targets(config)?
         .into_iter()
         .flat_map(|t| t.cache_paths())
         .collect::<HashSet<PathBuf>>()
         .into_iter()
-        .flat_map(|p| walkdir::WalkDir::new(p))
+        .flat_map(walkdir::WalkDir::new)
         .filter_map(|r| into_file_path(r.ok()?));
 
     for path in cache_paths {
@@ -62,7 +62,7 @@ pub fn save(to: PathBuf) -> anyhow::Result<()> {
         } else {
             move_file(
                 &path,
-                to.join("absolute").join(&path.strip_prefix("/").unwrap()),
+                to.join("absolute").join(path.strip_prefix("/").unwrap()),
             )?;
         }
     }
@@ -0,0 +1,352 @@
+//! Storage for the action cache.
+//!
+//! Backends store opaque blobs at paths like `ac/{key}` and `cas/{hash}`, which matches the
+//! layout of a bazel-remote style HTTP cache.
+
+use anyhow::Context;
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+pub trait CacheBackend: Send + Sync {
+    fn read(&self, path: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    fn write(&self, path: &str, contents: &[u8]) -> anyhow::Result<()>;
+
+    /// Copy the blob at `path` into the file `dest` without holding it in memory, returning
+    /// whether it exists.
+    fn read_to_file(&self, path: &str, dest: &Path) -> anyhow::Result<bool>;
+    /// Store the contents of the file `src` at `path` without holding them in memory.
+    fn write_from_file(&self, path: &str, src: &Path) -> anyhow::Result<()>;
+}
+
+fn copy_to_file(mut reader: impl std::io::Read, dest: &Path) -> anyhow::Result<()> {
+    let mut file = std::fs::File::create(dest).context(format!("Writing {dest:?}"))?;
+    std::io::copy(&mut reader, &mut file).context(format!("Writing {dest:?}"))?;
+    Ok(())
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct CacheConfig {
+    remote: Option<RemoteConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RemoteConfig {
+    url: String,
+
+    /// Whether to upload results to the remote cache, or only download from it.
+    #[serde(default = "default_upload")]
+    upload: bool,
+}
+
+fn default_upload() -> bool {
+    true
+}
+
+pub fn from_config(config: &CacheConfig) -> Box<dyn CacheBackend> {
+    let local = LocalBackend::new(crate::file_selector::CACHE_DIR);
+
+    match &config.remote {
+        None => Box::new(local),
+        Some(remote) => Box::new(TieredBackend {
+            local: Box::new(local),
+            remote: Box::new(HttpBackend::new(&remote.url)),
+            upload: remote.upload,
+        }),
+    }
+}
+
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalBackend { root: root.into() }
+    }
+
+    /// Writes to a temporary file next to `path` and renames it into place, so a crash or a
+    /// concurrent write of the same blob never leaves a partial one at `path`.
+    fn write_atomically(&self, path: &str, contents: impl std::io::Read) -> anyhow::Result<()> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = self.root.join(path);
+        std::fs::create_dir_all(path.parent().expect("explicit subdirectory"))?;
+        let temp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = copy_to_file(contents, &temp)
+            .and_then(|()| std::fs::rename(&temp, &path).context(format!("Writing {path:?}")));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        result
+    }
+}
+
+impl CacheBackend for LocalBackend {
+    fn read(&self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.root.join(path)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> anyhow::Result<()> {
+        self.write_atomically(path, contents)
+    }
+
+    fn read_to_file(&self, path: &str, dest: &Path) -> anyhow::Result<bool> {
+        let file = match std::fs::File::open(self.root.join(path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        copy_to_file(file, dest)?;
+        Ok(true)
+    }
+
+    fn write_from_file(&self, path: &str, src: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::open(src).context(format!("Reading {src:?}"))?;
+        self.write_atomically(path, file)
+    }
+}
+
+/// Reads and writes blobs with `GET` and `PUT` requests relative to a base URL.
+pub struct HttpBackend {
+    base: String,
+    agent: ureq::Agent,
+}
+
+impl HttpBackend {
+    pub fn new(base: &str) -> Self {
+        HttpBackend {
+            base: base.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(60))
+                .build(),
+        }
+    }
+}
+
+impl HttpBackend {
+    fn get(&self, path: &str) -> anyhow::Result<Option<ureq::Response>> {
+        let url = format!("{}/{path}", self.base);
+        match self.agent.get(&url).call() {
+            Ok(r) => Ok(Some(r)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(e).context(format!("GET {url}")),
+        }
+    }
+}
+
+impl CacheBackend for HttpBackend {
+    fn read(&self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let url = format!("{}/{path}", self.base);
+        let Some(response) = self.get(path)? else {
+            return Ok(None);
+        };
+
+        let mut bytes = Vec::new();
+        std::io::copy(&mut response.into_reader(), &mut bytes).context(format!("Reading {url}"))?;
+        Ok(Some(bytes))
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> anyhow::Result<()> {
+        let url = format!("{}/{path}", self.base);
+        self.agent
+            .put(&url)
+            .send_bytes(contents)
+            .context(format!("PUT {url}"))?;
+        Ok(())
+    }
+
+    fn read_to_file(&self, path: &str, dest: &Path) -> anyhow::Result<bool> {
+        let Some(response) = self.get(path)? else {
+            return Ok(false);
+        };
+        copy_to_file(response.into_reader(), dest)
+            .context(format!("Reading {}/{path}", self.base))?;
+        Ok(true)
+    }
+
+    fn write_from_file(&self, path: &str, src: &Path) -> anyhow::Result<()> {
+        let url = format!("{}/{path}", self.base);
+        let file = std::fs::File::open(src).context(format!("Reading {src:?}"))?;
+        // Without a length, the body would be sent chunked, which not every cache accepts.
+        let len = file.metadata()?.len();
+        self.agent
+            .put(&url)
+            .set("Content-Length", &len.to_string())
+            .send(file)
+            .context(format!("PUT {url}"))?;
+        Ok(())
+    }
+}
+
+/// Prefers the local backend, falling back to the remote and keeping a local copy of hits.
+///
+/// Remote errors are logged and otherwise ignored, so an unreachable cache only costs a rebuild.
+struct TieredBackend {
+    local: Box<dyn CacheBackend>,
+    remote: Box<dyn CacheBackend>,
+    upload: bool,
+}
+
+impl CacheBackend for TieredBackend {
+    fn read(&self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        if let Some(bytes) = self.local.read(path)? {
+            return Ok(Some(bytes));
+        }
+
+        match self.remote.read(path) {
+            Ok(Some(bytes)) => {
+                self.local.write(path, &bytes)?;
+                Ok(Some(bytes))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                eprintln!("Warning: reading remote cache: {e:#}");
+                Ok(None)
+            }
+        }
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> anyhow::Result<()> {
+        self.local.write(path, contents)?;
+
+        if self.upload {
+            if let Err(e) = self.remote.write(path, contents) {
+                eprintln!("Warning: writing remote cache: {e:#}");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_to_file(&self, path: &str, dest: &Path) -> anyhow::Result<bool> {
+        if self.local.read_to_file(path, dest)? {
+            return Ok(true);
+        }
+
+        match self.remote.read_to_file(path, dest) {
+            Ok(true) => {
+                self.local.write_from_file(path, dest)?;
+                Ok(true)
+            }
+            Ok(false) => Ok(false),
+            Err(e) => {
+                eprintln!("Warning: reading remote cache: {e:#}");
+                Ok(false)
+            }
+        }
+    }
+
+    fn write_from_file(&self, path: &str, src: &Path) -> anyhow::Result<()> {
+        self.local.write_from_file(path, src)?;
+
+        if self.upload {
+            if let Err(e) = self.remote.write_from_file(path, src) {
+                eprintln!("Warning: writing remote cache: {e:#}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiered(upload: bool) -> (tempfile::TempDir, tempfile::TempDir, TieredBackend) {
+        let local = tempfile::tempdir().unwrap();
+        let remote = tempfile::tempdir().unwrap();
+        let backend = TieredBackend {
+            local: Box::new(LocalBackend::new(local.path())),
+            remote: Box::new(LocalBackend::new(remote.path())),
+            upload,
+        };
+        (local, remote, backend)
+    }
+
+    #[test]
+    fn remote_hit_is_copied_locally() {
+        let (local, remote, backend) = tiered(true);
+        LocalBackend::new(remote.path())
+            .write("ac/key", b"entry")
+            .unwrap();
+
+        assert_eq!(backend.read("ac/key").unwrap(), Some(b"entry".to_vec()));
+        assert_eq!(
+            LocalBackend::new(local.path()).read("ac/key").unwrap(),
+            Some(b"entry".to_vec())
+        );
+    }
+
+    #[test]
+    fn remote_file_hit_is_copied_locally() {
+        let (local, remote, backend) = tiered(true);
+        LocalBackend::new(remote.path())
+            .write("cas/hash", b"blob")
+            .unwrap();
+
+        let dest = tempfile::NamedTempFile::new().unwrap();
+        assert!(backend.read_to_file("cas/hash", dest.path()).unwrap());
+        assert_eq!(std::fs::read(dest.path()).unwrap(), b"blob");
+        assert_eq!(
+            LocalBackend::new(local.path()).read("cas/hash").unwrap(),
+            Some(b"blob".to_vec())
+        );
+
+        assert!(!backend.read_to_file("cas/missing", dest.path()).unwrap());
+    }
+
+    #[test]
+    fn writes_upload_to_remote() {
+        let (_local, remote, backend) = tiered(true);
+
+        backend.write("ac/key", b"entry").unwrap();
+
+        assert_eq!(
+            LocalBackend::new(remote.path()).read("ac/key").unwrap(),
+            Some(b"entry".to_vec())
+        );
+    }
+
+    #[test]
+    fn download_only_does_not_upload() {
+        let (local, remote, backend) = tiered(false);
+
+        backend.write("ac/key", b"entry").unwrap();
+
+        assert_eq!(
+            LocalBackend::new(remote.path()).read("ac/key").unwrap(),
+            None
+        );
+        assert_eq!(
+            LocalBackend::new(local.path()).read("ac/key").unwrap(),
+            Some(b"entry".to_vec())
+        );
+    }
+
+    #[test]
+    fn unreachable_remote_is_a_miss() {
+        let local = tempfile::tempdir().unwrap();
+        let backend = TieredBackend {
+            local: Box::new(LocalBackend::new(local.path())),
+            remote: Box::new(HttpBackend::new("http://127.0.0.1:1")),
+            upload: true,
+        };
+
+        assert_eq!(backend.read("ac/key").unwrap(), None);
+        backend.write("ac/key", b"entry").unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+//! Ordering of targets so that every target comes after the targets it depends on.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Returns the nodes of `graph` ordered so each node follows all of its dependencies.
+///
+/// `graph` maps each node to the nodes it depends on. Dependencies that are not themselves keys
+/// of `graph` are ignored.
+pub fn topological_order(
+    graph: &BTreeMap<String, BTreeSet<String>>,
+) -> anyhow::Result<Vec<String>> {
+    let mut remaining = graph
+        .iter()
+        .map(|(node, deps)| {
+            let deps = deps
+                .iter()
+                .filter(|d| graph.contains_key(*d))
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            (node.clone(), deps)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut result = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .filter(|(_, deps)| deps.is_empty())
+            .map(|(node, _)| node.clone())
+            .collect::<Vec<_>>();
+
+        if ready.is_empty() {
+            let cycle = remaining.keys().cloned().collect::<Vec<_>>().join(", ");
+            anyhow::bail!("Dependency cycle between: {cycle}");
+        }
+
+        for node in ready {
+            remaining.remove(&node);
+            for deps in remaining.values_mut() {
+                deps.remove(&node);
+            }
+            result.push(node);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Returns `nodes` along with every node in `graph` that transitively depends on them.
+pub fn with_dependents(
+    graph: &BTreeMap<String, BTreeSet<String>>,
+    nodes: impl IntoIterator<Item = String>,
+) -> BTreeSet<String> {
+    let mut result = nodes.into_iter().collect::<BTreeSet<_>>();
+
+    loop {
+        let dependents = graph
+            .iter()
+            .filter(|(node, deps)| !result.contains(*node) && !deps.is_disjoint(&result))
+            .map(|(node, _)| node.clone())
+            .collect::<Vec<_>>();
+
+        if dependents.is_empty() {
+            return result;
+        }
+        result.extend(dependents);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> BTreeMap<String, BTreeSet<String>> {
+        edges
+            .iter()
+            .map(|(node, deps)| {
+                let deps = deps.iter().map(|d| d.to_string()).collect();
+                (node.to_string(), deps)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn independent_nodes_are_sorted() {
+        let order = topological_order(&graph(&[("b", &[]), ("a", &[])])).unwrap();
+
+        assert_eq!(order, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn dependency_comes_first() {
+        let order = topological_order(&graph(&[("a", &["b"]), ("b", &[])])).unwrap();
+
+        assert_eq!(order, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn transitive_dependencies() {
+        let order = topological_order(&graph(&[("a", &["b"]), ("b", &["c"]), ("c", &[])])).unwrap();
+
+        assert_eq!(order, vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn unknown_dependencies_are_ignored() {
+        let order = topological_order(&graph(&[("a", &["missing"])])).unwrap();
+
+        assert_eq!(order, vec!["a"]);
+    }
+
+    #[test]
+    fn dependents_are_transitive() {
+        let graph = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &[]), ("d", &[])]);
+
+        let affected = with_dependents(&graph, [String::from("c")]);
+
+        assert_eq!(affected, ["a", "b", "c"].map(String::from).into());
+    }
+
+    #[test]
+    fn cycle_is_error() {
+        let result = topological_order(&graph(&[("a", &["b"]), ("b", &["a"]), ("c", &[])]));
+
+        assert!(result.is_err());
+    }
+}
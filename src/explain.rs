@@ -0,0 +1,123 @@
+//! Records the inputs of cached actions so cache misses can be explained.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct Record {
+    /// Hash of each source file.
+    pub files: BTreeMap<PathBuf, String>,
+    /// Tool versions and environment, from `Target::cache_key_inputs`.
+    pub inputs: Vec<String>,
+}
+
+fn record_path(name: &str) -> PathBuf {
+    let hash = blake3::hash(name.as_bytes()).to_hex();
+    Path::new(crate::file_selector::CACHE_DIR).join(format!("records/{hash}.json"))
+}
+
+/// Save `record` as the inputs of the most recent cached run of the task `name`.
+pub fn save(name: &str, record: &Record) -> anyhow::Result<()> {
+    let path = record_path(name);
+    std::fs::create_dir_all(path.parent().expect("explicit subdirectory"))?;
+    std::fs::write(path, serde_json::to_vec(record)?)?;
+    Ok(())
+}
+
+/// Describe why the task `name` with inputs `current` missed the cache.
+pub fn explain(name: &str, current: &Record) -> anyhow::Result<String> {
+    let previous = match std::fs::read(record_path(name)) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(format!("{name} has no previous cached run"));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let diff = diff(&previous, current);
+    Ok(format!("{name} is not cached:\n{}", diff.trim_end()))
+}
+
+/// Describe a task that always runs, since its target has no source files to key it on or its
+/// build can't be restored.
+pub fn not_cacheable(name: &str) -> String {
+    format!("{name} is not cacheable, so it always runs")
+}
+
+/// Describe a task whose cache entry exists without the outputs it lists.
+pub fn missing_outputs(name: &str) -> String {
+    format!("{name} is not cached: its entry was found, but its outputs are missing from the cache")
+}
+
+fn diff(previous: &Record, current: &Record) -> String {
+    let mut result = String::new();
+
+    for (path, hash) in &current.files {
+        match previous.files.get(path) {
+            None => writeln!(result, "  added: {}", path.display()).unwrap(),
+            Some(h) if h != hash => writeln!(result, "  modified: {}", path.display()).unwrap(),
+            Some(_) => {}
+        }
+    }
+    for path in previous.files.keys() {
+        if !current.files.contains_key(path) {
+            writeln!(result, "  removed: {}", path.display()).unwrap();
+        }
+    }
+
+    if previous.inputs != current.inputs {
+        writeln!(result, "  tool versions or environment changed").unwrap();
+    }
+
+    if result.is_empty() {
+        writeln!(
+            result,
+            "  inputs match the previous cached run, its entry is missing"
+        )
+        .unwrap();
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(files: &[(&str, &str)]) -> Record {
+        Record {
+            files: files
+                .iter()
+                .map(|(path, hash)| (PathBuf::from(path), hash.to_string()))
+                .collect(),
+            inputs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn file_changes() {
+        let previous = record(&[("kept", "a"), ("modified", "b"), ("removed", "c")]);
+        let current = record(&[("kept", "a"), ("modified", "changed"), ("added", "d")]);
+
+        assert_eq!(
+            diff(&previous, &current),
+            "  added: added\n  modified: modified\n  removed: removed\n"
+        );
+    }
+
+    #[test]
+    fn input_changes() {
+        let previous = record(&[("kept", "a")]);
+        let mut current = record(&[("kept", "a")]);
+        current.inputs.push(String::from("rustc 1.0"));
+
+        assert_eq!(
+            diff(&previous, &current),
+            "  tool versions or environment changed\n"
+        );
+    }
+}
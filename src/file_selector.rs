@@ -4,8 +4,40 @@ use glob::{MatchOptions, Pattern, PatternError};
 use std::{
     collections::HashSet,
     path::{Path, PathBuf},
+    sync::Mutex,
 };
 
+/// Directory Gentle caches results in.
+pub const CACHE_DIR: &str = ".gentle_cache";
+
+/// Directories Gentle writes to besides [`CACHE_DIR`], like the build output directory.
+static GENERATED_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Leave `dir` out of every selector, since Gentle writes to it.
+pub fn exclude_generated(dir: &Path) {
+    let Ok(dir) = simplify(dir) else {
+        return;
+    };
+    let mut dirs = GENERATED_DIRS.lock().unwrap();
+    if !dirs.contains(&dir) {
+        dirs.push(dir);
+    }
+}
+
+/// Whether `path` is in a directory Gentle writes to, so it's never a source file, even when
+/// targets walk their directories or `.gitignore` doesn't cover it.
+pub fn is_generated(path: &Path) -> bool {
+    let Ok(path) = simplify(path) else {
+        return false;
+    };
+    path.starts_with(CACHE_DIR)
+        || GENERATED_DIRS
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|dir| path.starts_with(dir))
+}
+
 #[derive(Debug)]
 pub struct FileSelector {
     files: HashSet<PathBuf>,
@@ -18,9 +50,23 @@ impl FileSelector {
     }
 
     pub fn list(&self) -> anyhow::Result<HashSet<PathBuf>> {
+        let result = self.list_allow_empty()?;
+
+        if result.is_empty() {
+            anyhow::bail!("Found no files");
+        }
+
+        Ok(result)
+    }
+
+    /// Like [`FileSelector::list`], but without failing when no files are included.
+    pub fn list_allow_empty(&self) -> anyhow::Result<HashSet<PathBuf>> {
         let mut result = HashSet::new();
 
-        for entry in walkdir::WalkDir::new("./") {
+        let walk = walkdir::WalkDir::new("./")
+            .into_iter()
+            .filter_entry(|e| !is_generated(e.path()));
+        for entry in walk {
             let entry = entry?;
             if !entry.file_type().is_file() {
                 continue;
@@ -34,23 +80,26 @@ impl FileSelector {
             result.insert(path.to_path_buf());
         }
 
-        if result.is_empty() {
-            anyhow::bail!("Found no files");
-        }
-
         Ok(result)
     }
 
-    fn includes(&self, path: impl AsRef<Path>) -> bool {
-        let path = simplify(path);
+    pub fn includes(&self, path: impl AsRef<Path>) -> bool {
+        let Ok(path) = simplify(path) else {
+            return false;
+        };
+        if is_generated(&path) {
+            return false;
+        }
 
         let included_by_file = path.ancestors().any(|a| self.files.contains(a));
         if included_by_file {
             return true;
         }
 
-        let mut match_options = MatchOptions::default();
-        match_options.require_literal_separator = true;
+        let match_options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
 
         self.globs
             .iter()
@@ -71,8 +120,12 @@ pub struct FileSelectorBuilder {
 }
 
 impl FileSelectorBuilder {
+    /// Includes the file or directory at `path`, unless it's outside of the current directory,
+    /// where [`FileSelector::list`] wouldn't find it anyway.
     pub fn path(mut self, path: impl AsRef<Path>) -> Self {
-        self.files.insert(self.subdir.join(simplify(path)));
+        if let Ok(path) = simplify(path) {
+            self.files.insert(self.subdir.join(path));
+        }
         self
     }
 
@@ -83,7 +136,8 @@ impl FileSelectorBuilder {
     }
 
     pub fn set_subdir(mut self, path: impl AsRef<Path>) -> Self {
-        self.subdir = simplify(path).to_path_buf();
+        let path = path.as_ref();
+        self.subdir = simplify(path).unwrap_or_else(|_| path.to_path_buf());
         self
     }
 
@@ -95,7 +149,9 @@ impl FileSelectorBuilder {
     }
 }
 
-fn simplify(path: impl AsRef<Path>) -> PathBuf {
+/// `path` without `.` components and with `..` components applied, failing for paths that go
+/// above where they start, like `../dir`.
+pub fn simplify(path: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
     use std::path::Component;
 
     let path = path.as_ref();
@@ -107,17 +163,20 @@ fn simplify(path: impl AsRef<Path>) -> PathBuf {
         }
 
         if comp == Component::ParentDir {
-            stack.pop().expect("parent dir escapes path");
+            if stack.pop().is_none() {
+                anyhow::bail!("{path:?} escapes its starting directory");
+            }
             continue;
         }
 
         stack.push(comp);
     }
 
-    stack.into_iter().collect()
+    Ok(stack.into_iter().collect())
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -210,11 +269,26 @@ mod tests {
         assert_eq!(selector.includes("some/another/foo.txt"), true);
     }
 
+    #[test]
+    fn excludes_generated_dirs() {
+        let selector = FileSelector::builder().path(".").build();
+        exclude_generated(Path::new("./generated/out"));
+
+        assert_eq!(selector.includes("./src/main.rs"), true);
+        assert_eq!(selector.includes("./.gentle_cache/cas/hash"), false);
+        assert_eq!(selector.includes("generated/out/app.tar"), false);
+        assert_eq!(selector.includes("generated/other.txt"), true);
+    }
+
     #[test]
     fn simplify_cases() {
-        assert_eq!(simplify("some/dir"), PathBuf::from("some/dir"));
-        assert_eq!(simplify("./some/dir"), PathBuf::from("some/dir"));
-        assert_eq!(simplify("dir/.."), PathBuf::from(""));
-        assert_eq!(simplify("dir/../some/path"), PathBuf::from("some/path"));
+        assert_eq!(simplify("some/dir").unwrap(), PathBuf::from("some/dir"));
+        assert_eq!(simplify("./some/dir").unwrap(), PathBuf::from("some/dir"));
+        assert_eq!(simplify("dir/..").unwrap(), PathBuf::from(""));
+        assert_eq!(
+            simplify("dir/../some/path").unwrap(),
+            PathBuf::from("some/path")
+        );
+        assert!(simplify("dir/../../some/path").is_err());
     }
 }
@@ -0,0 +1,68 @@
+//! Queries against the git repository Gentle runs in.
+
+use anyhow::Context;
+use std::{path::PathBuf, process::Command};
+
+/// Files that differ between the working tree and the merge base of `since` and `HEAD`,
+/// including untracked files.
+///
+/// Paths are relative to the current directory.
+pub fn changed_files(since: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let merge_base = git(&["merge-base", since, "HEAD"])?;
+    let merge_base = merge_base.trim();
+
+    let changed = git(&["diff", "--name-only", "--relative", merge_base])?;
+    let untracked = git(&["ls-files", "--others", "--exclude-standard"])?;
+
+    Ok(changed
+        .lines()
+        .chain(untracked.lines())
+        .filter(|l| !l.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// The commit `rev` refers to.
+pub fn resolve(rev: &str) -> anyhow::Result<String> {
+    let commit = git(&["rev-parse", "--verify", &format!("{rev}^{{commit}}")])?;
+    Ok(commit.trim().to_string())
+}
+
+/// Directory with the objects and refs of the repository, shared between its worktrees.
+pub fn common_dir() -> anyhow::Result<PathBuf> {
+    Ok(PathBuf::from(
+        git(&["rev-parse", "--path-format=absolute", "--git-common-dir"])?.trim(),
+    ))
+}
+
+/// Path of the current directory relative to the root of the repository, empty at the root.
+pub fn prefix() -> anyhow::Result<PathBuf> {
+    Ok(PathBuf::from(git(&["rev-parse", "--show-prefix"])?.trim()))
+}
+
+/// Whether `path`, relative to the root of the repository, exists in `commit`.
+pub fn exists_at(commit: &str, path: &str) -> anyhow::Result<bool> {
+    let status = Command::new("git")
+        .args(["cat-file", "-e", &format!("{commit}:{path}")])
+        .output()
+        .context("Running git")?
+        .status;
+    Ok(status.success())
+}
+
+fn git(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Running git")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed:\n{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
@@ -1,5 +1,9 @@
 use anyhow::Context;
-use std::{collections::BTreeSet, fs::File, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    path::PathBuf,
+};
 
 pub fn hash_files(files: impl IntoIterator<Item = PathBuf>) -> anyhow::Result<blake3::Hash> {
     let mut hasher = blake3::Hasher::new();
@@ -12,3 +16,18 @@ pub fn hash_files(files: impl IntoIterator<Item = PathBuf>) -> anyhow::Result<bl
 
     Ok(hasher.finalize())
 }
+
+pub fn hash_each(
+    files: impl IntoIterator<Item = PathBuf>,
+) -> anyhow::Result<BTreeMap<PathBuf, blake3::Hash>> {
+    let mut result = BTreeMap::new();
+
+    for path in files {
+        let mut hasher = blake3::Hasher::new();
+        let mut file = File::open(&path).context(format!("Opening {path:?}"))?;
+        std::io::copy(&mut file, &mut hasher)?;
+        result.insert(path, hasher.finalize());
+    }
+
+    Ok(result)
+}
@@ -3,19 +3,31 @@ use indicatif::*;
 use is_terminal::*;
 use serde::*;
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::Display,
     path::*,
+    str::FromStr,
     sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
 use structopt::*;
 
+mod action_cache;
+use action_cache::ActionCache;
+
 mod cache;
+mod cache_backend;
+mod dependency_graph;
+mod explain;
 mod file_selector;
+use file_selector::FileSelector;
+mod git;
 mod hash_files;
 
+mod query;
+mod watch;
+
 mod multi_runner;
 use multi_runner::*;
 
@@ -40,12 +52,16 @@ pub enum Command {
         to: PathBuf,
     },
     LockfileHash,
+    /// List discovered targets and how Gentle sees them.
+    Query(query::Query),
+    /// Perform actions whenever the files of targets change, e.g. `gentle watch test`.
+    Watch(ActionCommand),
 
     Do(ActionCommand),
 
-    // TODO(shelbyd): Allow multiple actions.
-    #[structopt(flatten)]
-    Action(Action),
+    /// Shorthand for `do <actions>...`, e.g. `gentle format lint test`.
+    #[structopt(external_subcommand)]
+    Action(Vec<String>),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, StructOpt)]
@@ -53,16 +69,41 @@ pub struct ActionCommand {
     #[structopt(long)]
     filter: Option<String>,
 
-    #[structopt(subcommand)]
-    verb: Action,
+    /// Only run targets affected by files changed since this git ref, and their dependents.
+    #[structopt(long)]
+    since: Option<String>,
+
+    /// Explain why each task missed the cache.
+    #[structopt(long)]
+    explain: bool,
+
+    /// Run every task even after failures, then report all of them.
+    #[structopt(long)]
+    keep_going: bool,
+
+    /// Only report formatting issues, the default.
+    #[structopt(long, conflicts_with = "fix")]
+    #[allow(dead_code)]
+    check: bool,
+
+    /// Fix formatting and lint issues where possible instead of reporting them.
+    #[structopt(long)]
+    fix: bool,
+
+    #[structopt(flatten)]
+    build: Build,
+
+    /// Actions to perform, in order, on each target.
+    #[structopt(required = true, possible_values = &Action::NAMES)]
+    actions: Vec<Action>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, StructOpt)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Action {
     Test,
     Lint,
     Format,
-    Build(Build),
+    Build,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, StructOpt)]
@@ -75,11 +116,20 @@ pub struct Build {
     out: PathBuf,
 }
 
+/// Whether format and lint actions only check files or also fix them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    Check,
+    Fix,
+}
+
 impl Action {
-    fn can_cache_success(&self) -> bool {
+    const NAMES: [&'static str; 4] = ["test", "lint", "format", "build"];
+
+    fn can_fix(&self) -> bool {
         match self {
-            Action::Test | Action::Lint | Action::Format => true,
-            Action::Build(_) => false,
+            Action::Lint | Action::Format => true,
+            Action::Test | Action::Build => false,
         }
     }
 }
@@ -90,14 +140,48 @@ impl Display for Action {
             Action::Test => write!(f, "test"),
             Action::Lint => write!(f, "lint"),
             Action::Format => write!(f, "format"),
-            Action::Build(_) => write!(f, "build"),
+            Action::Build => write!(f, "build"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "test" => Ok(Action::Test),
+            "lint" => Ok(Action::Lint),
+            "format" => Ok(Action::Format),
+            "build" => Ok(Action::Build),
+            _ => anyhow::bail!("Unknown action {s:?}"),
         }
     }
 }
 
 #[derive(Deserialize, Default)]
 struct Config {
+    #[serde(default)]
     skip: HashSet<String>,
+
+    /// Extra dependencies between targets, keyed by the address of the dependent target.
+    #[serde(default)]
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+
+    #[serde(default)]
+    cache: cache_backend::CacheConfig,
+
+    #[serde(default)]
+    go: targets::GoConfig,
+
+    #[serde(default)]
+    docker: targets::DockerConfig,
+
+    #[serde(default)]
+    buf: targets::BufConfig,
+
+    #[serde(default)]
+    terraform: targets::TerraformConfig,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -109,46 +193,218 @@ fn main() -> anyhow::Result<()> {
         Config::default()
     };
 
-    let action = match options.command {
+    let command = match options.command {
         Command::CacheLoad { from } => return cache::load(from),
-        Command::CacheSave { to } => return cache::save(to),
+        Command::CacheSave { to } => return cache::save(&config, to),
         Command::LockfileHash => {
-            let files = targets()?.into_iter().flat_map(|t| t.lock_files());
+            let files = targets(&config)?.into_iter().flat_map(|t| t.lock_files());
             println!("{}", hash_files::hash_files(files)?.to_hex());
             return Ok(());
         }
-        Command::Do(action) => action,
-        Command::Action(verb) => ActionCommand { verb, filter: None },
+        Command::Query(query) => return query::run(&config, query),
+        Command::Watch(command) => {
+            file_selector::exclude_generated(&command.build.out);
+            let mut watcher = watch::Watcher::new()?;
+            let mut changed = since_changed_files(&command)?;
+
+            loop {
+                if let Err(e) = run(&config, &command, changed.as_deref()) {
+                    eprintln!("{e:?}");
+                }
+
+                eprintln!("Watching for changes");
+                changed = Some(watcher.wait_for_changes()?);
+            }
+        }
+        Command::Do(command) => command,
+        Command::Action(args) => {
+            ActionCommand::from_iter(std::iter::once(String::from("do")).chain(args))
+        }
     };
 
-    let targets = targets::targets()?
-        .into_iter()
-        .filter(|t| should_run(&t.to_string(), &config.skip, &action.filter))
-        .collect::<Vec<_>>();
+    file_selector::exclude_generated(&command.build.out);
+    let changed = since_changed_files(&command)?;
+    run(&config, &command, changed.as_deref())
+}
+
+fn since_changed_files(command: &ActionCommand) -> anyhow::Result<Option<Vec<PathBuf>>> {
+    match &command.since {
+        None => Ok(None),
+        Some(since) => Ok(Some(git::changed_files(since)?)),
+    }
+}
+
+/// Perform the actions of `command` on the selected targets.
+///
+/// When `changed` is provided, only targets including those files and their dependents are run.
+fn run(
+    config: &Config,
+    command: &ActionCommand,
+    changed: Option<&[PathBuf]>,
+) -> anyhow::Result<()> {
+    let all_targets = targets::targets(config)?;
+    let addresses = all_targets
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<HashSet<_>>();
+
+    let mut targets = BTreeMap::new();
+    let mut dependencies = BTreeMap::new();
+    for target in all_targets {
+        let address = target.to_string();
+        if !should_run(&address, &config.skip, &command.filter) {
+            continue;
+        }
+
+        let mut deps = target.dependencies()?;
+        deps.extend(
+            config
+                .dependencies
+                .get(&address)
+                .cloned()
+                .unwrap_or_default(),
+        );
+        if let Some(unknown) = deps.iter().find(|d| !addresses.contains(*d)) {
+            anyhow::bail!("{address} depends on unknown target {unknown}");
+        }
+
+        dependencies.insert(address.clone(), deps.into_iter().collect::<BTreeSet<_>>());
+        targets.insert(address, Task::new(target));
+    }
+
+    if let Some(changed) = changed {
+        let mut affected = Vec::new();
+        for (address, task) in &targets {
+            let src_files = task.target.src_files()?;
+            if is_affected(task.target.dir(), src_files.as_ref(), changed) {
+                affected.push(address.clone());
+            }
+        }
+
+        let affected = dependency_graph::with_dependents(&dependencies, affected);
+        targets.retain(|address, _| affected.contains(address));
+        dependencies.retain(|address, _| affected.contains(address));
+    }
+
+    run_covering_targets(&mut targets, &mut dependencies)?;
+
+    let order = dependency_graph::topological_order(&dependencies)?;
 
     let progress: Box<dyn ProgressListener> = if std::env::var("CI") == Ok(String::from("true")) {
-        Box::new(ContinuousIntegrationProgress::new(targets.len()))
+        Box::new(ContinuousIntegrationProgress::new(
+            targets.len() * command.actions.len(),
+        ))
     } else if std::io::stderr().is_terminal() {
         Box::new(TermProgress::new())
     } else {
         Box::new(NullProgressListener)
     };
-    let mut runner = ParRunner::new(progress);
-
-    for target in targets {
-        let action = action.clone();
-        runner
-            .run(&format!("{} {target}", action.verb), move || {
-                maybe_cache_success(&action.verb, &*target, || match &action.verb {
-                    Action::Test => target.perform_test(),
-                    Action::Lint => target.perform_lint(),
-                    Action::Format => target.perform_format(),
-                    Action::Build(build) => target.perform_build(build),
+    let mut runner = ParRunner::new(progress).keep_going(command.keep_going);
+
+    let build = Arc::new(command.build.clone());
+    let run_options = Arc::new(RunOptions {
+        cache: ActionCache::new(cache_backend::from_config(&config.cache)),
+        explain: command.explain,
+        mode: command.mode(),
+    });
+    for (i, &action) in command.actions.iter().enumerate() {
+        for address in &order {
+            let task = Arc::clone(&targets[address]);
+
+            let deps = task_dependencies(&command.actions, i, address, &dependencies[address]);
+
+            let build = Arc::clone(&build);
+            let run_options = Arc::clone(&run_options);
+            let name = format!("{action} {address}");
+            let task_name = name.clone();
+            runner
+                .run_after(&name, &deps, move || {
+                    maybe_cache(&task_name, action, &task, &run_options, &build, |build| {
+                        perform(action, &*task.target, build, run_options.mode)
+                    })
                 })
-            })
-            .map_err(|(id, err)| err.context(id))?;
+                .map_err(|(id, err)| err.context(id))?;
+        }
+    }
+
+    if !command.keep_going {
+        runner.into_wait().map_err(|(id, err)| err.context(id))?;
+        return Ok(());
+    }
+
+    let failures = runner.into_failures();
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    for (name, failure) in &failures {
+        if let Failure::Failed(err) = failure {
+            eprintln!("{name} failed:\n{err:?}\n");
+        }
+    }
+
+    eprintln!("Failure summary:");
+    for (name, failure) in &failures {
+        match failure {
+            Failure::Failed(_) => eprintln!("  {name}: failed"),
+            Failure::Skipped { dependency } => {
+                eprintln!("  {name}: skipped, {dependency} did not succeed")
+            }
+        }
+    }
+
+    anyhow::bail!("{} tasks did not succeed", failures.len())
+}
+
+/// Whether any of the `changed` files affect a target in `dir` with `src_files`.
+///
+/// Source files are listed from those that exist, so a deleted file affects every target whose
+/// directory it was in.
+fn is_affected(dir: &Path, src_files: Option<&FileSelector>, changed: &[PathBuf]) -> bool {
+    let Some(files) = src_files else {
+        return true;
+    };
+    let dir = file_selector::simplify(dir).unwrap_or_else(|_| dir.to_path_buf());
+
+    changed.iter().any(|f| {
+        files.includes(f)
+            || (!f.exists() && file_selector::simplify(f).is_ok_and(|f| f.starts_with(&dir)))
+    })
+}
+
+/// Run targets that cover all of their selected members in place of those members, and drop
+/// covering targets when only some of their members are selected.
+fn run_covering_targets(
+    targets: &mut BTreeMap<String, Arc<Task>>,
+    dependencies: &mut BTreeMap<String, BTreeSet<String>>,
+) -> anyhow::Result<()> {
+    let mut covered_by = BTreeMap::new();
+    let mut partial = HashSet::new();
+    for (address, task) in targets.iter() {
+        let members = task.target.members()?;
+        if members.is_empty() {
+            continue;
+        }
+
+        if members.iter().all(|m| targets.contains_key(m)) {
+            for member in members {
+                covered_by.insert(member, address.clone());
+            }
+        } else {
+            partial.insert(address.clone());
+        }
+    }
+
+    let keep = |address: &String| !covered_by.contains_key(address) && !partial.contains(address);
+    targets.retain(|address, _| keep(address));
+    dependencies.retain(|address, _| keep(address));
+
+    for deps in dependencies.values_mut() {
+        *deps = deps
+            .iter()
+            .map(|d| covered_by.get(d).unwrap_or(d).clone())
+            .collect();
     }
-    runner.into_wait().map_err(|(id, err)| err.context(id))?;
 
     Ok(())
 }
@@ -167,36 +423,205 @@ fn should_run(target: &str, skip: &HashSet<String>, filter: &Option<String>) ->
     true
 }
 
-fn maybe_cache_success(
-    action: &Action,
-    target: &dyn Target,
-    f: impl FnOnce() -> anyhow::Result<()>,
-) -> anyhow::Result<()> {
-    if !action.can_cache_success() {
-        return f();
+/// Tasks that the `i`th action on `address` waits for: the same action on the target's
+/// dependencies, and the previous action on the target itself.
+fn task_dependencies(
+    actions: &[Action],
+    i: usize,
+    address: &str,
+    dependencies: &BTreeSet<String>,
+) -> Vec<String> {
+    let action = actions[i];
+    let mut deps = dependencies
+        .iter()
+        .map(|d| format!("{action} {d}"))
+        .collect::<Vec<_>>();
+    if let Some(previous) = i.checked_sub(1).map(|i| actions[i]) {
+        deps.push(format!("{previous} {address}"));
+    }
+    deps
+}
+
+impl ActionCommand {
+    fn mode(&self) -> Mode {
+        if self.fix {
+            Mode::Fix
+        } else {
+            Mode::Check
+        }
     }
+}
+
+fn perform(action: Action, target: &dyn Target, build: &Build, mode: Mode) -> anyhow::Result<()> {
+    match action {
+        Action::Test => target.perform_test(),
+        Action::Lint => target.perform_lint(mode),
+        Action::Format => target.perform_format(mode),
+        Action::Build => target.perform_build(build),
+    }
+}
 
-    let files = match target.src_files()? {
-        None => return f(),
-        Some(f) => f,
+/// A target along with state shared between the actions performed on it.
+struct Task {
+    target: Box<dyn Target>,
+    inputs: Mutex<Option<Option<Arc<Inputs>>>>,
+}
+
+struct Inputs {
+    hash: String,
+    record: explain::Record,
+}
+
+impl Task {
+    fn new(target: Box<dyn Target>) -> Arc<Self> {
+        Arc::new(Task {
+            target,
+            inputs: Default::default(),
+        })
+    }
+
+    /// The target's source files and cache key inputs, computed at most once per invocation.
+    fn inputs(&self) -> anyhow::Result<Option<Arc<Inputs>>> {
+        let mut inputs = self.inputs.lock().unwrap();
+        if let Some(i) = &*inputs {
+            return Ok(i.clone());
+        }
+
+        let computed = match self.target.src_files()? {
+            None => None,
+            Some(files) => {
+                let files = files.list().context("Listing files")?;
+                let record = explain::Record {
+                    files: hash_files::hash_each(files)
+                        .context("Hashing files")?
+                        .into_iter()
+                        .map(|(path, hash)| (path, hash.to_hex().to_string()))
+                        .collect(),
+                    inputs: self.target.cache_key_inputs()?,
+                };
+
+                let mut hasher = blake3::Hasher::new();
+                for (path, hash) in &record.files {
+                    hasher.update(path.to_string_lossy().as_bytes());
+                    hasher.update(b"\0");
+                    hasher.update(hash.as_bytes());
+                }
+                for input in &record.inputs {
+                    hasher.update(input.as_bytes());
+                    hasher.update(b"\0");
+                }
+
+                Some(Arc::new(Inputs {
+                    hash: hasher.finalize().to_hex().to_string(),
+                    record,
+                }))
+            }
+        };
+
+        *inputs = Some(computed.clone());
+        Ok(computed)
+    }
+
+    /// Recompute inputs the next time they are needed, after source files were modified.
+    fn invalidate_inputs(&self) {
+        *self.inputs.lock().unwrap() = None;
+    }
+}
+
+struct RunOptions {
+    cache: ActionCache,
+    explain: bool,
+    mode: Mode,
+}
+
+fn maybe_cache(
+    name: &str,
+    action: Action,
+    task: &Task,
+    options: &RunOptions,
+    build: &Build,
+    f: impl FnOnce(&Build) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let inputs = match task.inputs()? {
+        Some(i) if action != Action::Build || task.target.caches_build() => i,
+        _ => {
+            if options.explain {
+                eprintln!("{}", explain::not_cacheable(name));
+            }
+            return f(build);
+        }
+    };
+    let action_inputs = match action {
+        Action::Lint => task.target.lint_key_inputs()?,
+        _ => Vec::new(),
     };
+    let key = action_cache::action_key(&inputs.hash, action, &action_inputs);
+    let cache = &options.cache;
 
-    let files = files.list().context("Listing files")?;
-    let hash = hash_files::hash_files(files)
-        .context("Hashing files")?
-        .to_hex();
-    let cache_path = PathBuf::from(format!(".gentle_cache/successes/{hash}/{action}"));
+    let mut outputs_missing = false;
+    if let Some(entry) = cache.get(&key)? {
+        // Otherwise outputs are missing from the cache, and the action runs as if it missed.
+        if cache.restore_outputs(&entry, &build.out)? {
+            return Ok(());
+        }
+        outputs_missing = true;
+    }
+
+    if options.mode == Mode::Fix && action.can_fix() {
+        // Fixing modifies source files, so the result is not cached. A cached check still shows
+        // there is nothing to fix.
+        f(build)?;
+        task.invalidate_inputs();
 
-    if cache_path.exists() {
+        if let Some(fixed) = task.inputs()? {
+            report_fixed(name, &inputs.record, &fixed.record);
+        }
         return Ok(());
     }
 
-    f()?;
+    if options.explain && outputs_missing {
+        eprintln!("{}", explain::missing_outputs(name));
+    } else if options.explain {
+        eprintln!("{}", explain::explain(name, &inputs.record)?);
+    }
+
+    let entry = if action == Action::Build {
+        let staging = Path::new(file_selector::CACHE_DIR).join(format!("staging/{key}"));
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+        std::fs::create_dir_all(&staging)?;
 
-    std::fs::create_dir_all(cache_path.parent().expect("explicit subdirectory"))?;
-    std::fs::File::create(cache_path).context("Creating cache marker")?;
+        f(&Build {
+            out: staging.clone(),
+        })?;
+        let entry = cache.store_outputs(&staging).context("Storing outputs")?;
+        std::fs::remove_dir_all(&staging)?;
 
-    Ok(())
+        if !cache.restore_outputs(&entry, &build.out)? {
+            anyhow::bail!("Outputs of {name} are missing from the cache right after storing them");
+        }
+        entry
+    } else {
+        f(build)?;
+        Default::default()
+    };
+
+    cache.put(&key, &entry).context("Storing cache entry")?;
+    explain::save(name, &inputs.record).context("Saving inputs record")
+}
+
+fn report_fixed(name: &str, before: &explain::Record, after: &explain::Record) {
+    let changed = after
+        .files
+        .iter()
+        .filter(|(path, hash)| before.files.get(*path) != Some(hash))
+        .map(|(path, _)| format!("  {}", path.display()))
+        .collect::<Vec<_>>();
+
+    if !changed.is_empty() {
+        eprintln!("{name} changed:\n{}", changed.join("\n"));
+    }
 }
 
 struct TermProgress {
@@ -335,3 +760,93 @@ impl Drop for ContinuousIntegrationProgress {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_run_in_order_and_stop_on_failure() {
+        let actions = [Action::Format, Action::Lint, Action::Test];
+        let dependencies = BTreeMap::from([
+            ("//a".to_string(), BTreeSet::new()),
+            ("//b".to_string(), BTreeSet::from(["//a".to_string()])),
+        ]);
+        let ran = Arc::new(Mutex::new(Vec::new()));
+
+        let mut runner = ParRunner::with_parallel(4, NullProgressListener);
+        let mut result = Ok(());
+        'schedule: for i in 0..actions.len() {
+            for (address, deps) in &dependencies {
+                let name = format!("{} {address}", actions[i]);
+                let deps = task_dependencies(&actions, i, address, deps);
+                let ran = Arc::clone(&ran);
+                result = runner.run_after(&name.clone(), &deps, move || {
+                    ran.lock().unwrap().push(name.clone());
+                    match name.as_str() {
+                        "lint //a" => Err(name),
+                        _ => Ok(()),
+                    }
+                });
+                if result.is_err() {
+                    break 'schedule;
+                }
+            }
+        }
+        if result.is_ok() {
+            result = runner.into_wait();
+        }
+
+        assert_eq!(
+            result,
+            Err(("lint //a".to_string(), "lint //a".to_string()))
+        );
+        let ran = ran.lock().unwrap();
+        let position = |name: &str| ran.iter().position(|n| n == name);
+        assert!(position("format //a").unwrap() < position("lint //a").unwrap());
+        for skipped in ["test //a", "lint //b", "test //b"] {
+            assert_eq!(position(skipped), None, "{skipped} ran");
+        }
+    }
+
+    #[test]
+    fn deleted_file_affects_target_in_its_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let deleted = dir.path().join("pkg/deleted.go");
+        let existing = dir.path().join("pkg/other.txt");
+        std::fs::create_dir_all(existing.parent().unwrap()).unwrap();
+        std::fs::write(&existing, "").unwrap();
+        let src_files = FileSelector::builder().build();
+
+        let affected = |target_dir: &str, changed: &Path| {
+            is_affected(
+                &dir.path().join(target_dir),
+                Some(&src_files),
+                &[changed.to_path_buf()],
+            )
+        };
+        assert!(affected("pkg", &deleted));
+        assert!(affected("", &deleted));
+        assert!(!affected("other", &deleted));
+        assert!(!affected("pkg", &existing));
+    }
+
+    #[test]
+    fn fix_flag_selects_fix_mode() {
+        let parse = |args: &[&str]| ActionCommand::from_iter_safe(args);
+        let mode = |args: &[&str]| parse(args).unwrap().mode();
+        assert_eq!(mode(&["do", "format"]), Mode::Check);
+        assert_eq!(mode(&["do", "format", "--check"]), Mode::Check);
+        assert_eq!(mode(&["do", "format", "--fix"]), Mode::Fix);
+        assert!(parse(&["do", "format", "--check", "--fix"]).is_err());
+    }
+
+    #[test]
+    fn only_format_and_lint_fix() {
+        let fixable = [Action::Test, Action::Lint, Action::Format, Action::Build]
+            .into_iter()
+            .filter(Action::can_fix)
+            .collect::<Vec<_>>();
+        assert_eq!(fixable, [Action::Lint, Action::Format]);
+    }
+}
@@ -1,18 +1,41 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     sync::mpsc::{channel, Receiver, Sender},
     thread::{spawn, JoinHandle},
 };
 
 pub type RunResult<E> = Result<(), (String, E)>;
 
+type Finished<E> = (usize, Result<(), E>);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Failure<E> {
+    Failed(E),
+    Skipped { dependency: String },
+}
+
+/// A task waiting for its dependencies to finish or for a free thread.
+struct Queued<E> {
+    name: String,
+    dependencies: Vec<String>,
+    f: Box<dyn FnOnce() -> Result<(), E> + Send>,
+}
+
 pub struct ParRunner<E: Send + 'static, P: ProgressListener> {
     max_threads: usize,
     handles: HashMap<usize, JoinHandle<()>>,
     names: HashMap<usize, String>,
 
-    receiver: Receiver<(usize, Result<(), E>)>,
-    sender: Sender<(usize, Result<(), E>)>,
+    queue: VecDeque<Queued<E>>,
+    /// Names of queued and running tasks.
+    unfinished: HashSet<String>,
+
+    keep_going: bool,
+    failed: HashSet<String>,
+    failures: Vec<(String, Failure<E>)>,
+
+    receiver: Receiver<Finished<E>>,
+    sender: Sender<Finished<E>>,
 
     progress: P,
 }
@@ -38,28 +61,113 @@ impl<E: Send + 'static, P: ProgressListener> ParRunner<E, P> {
             max_threads,
             handles: Default::default(),
             names: Default::default(),
+            queue: Default::default(),
+            unfinished: Default::default(),
+            keep_going: false,
+            failed: Default::default(),
+            failures: Default::default(),
             sender,
             receiver,
             progress,
         }
     }
 
+    /// Keep running remaining tasks after a failure instead of returning it.
+    ///
+    /// Tasks that depend on a failed task are skipped. Collect failures with
+    /// [`ParRunner::into_failures`].
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    #[allow(dead_code)]
     pub fn run(
         &mut self,
         name: &str,
         f: impl FnOnce() -> Result<(), E> + Send + 'static,
+    ) -> RunResult<E> {
+        self.run_after(name, &[], f)
+    }
+
+    /// Run `f` once every task named in `dependencies` has finished successfully.
+    ///
+    /// Dependencies must have been added before this call, tasks that were never added are
+    /// treated as already finished. Returns without waiting for the dependencies, tasks added
+    /// later start as soon as a thread is free.
+    pub fn run_after(
+        &mut self,
+        name: &str,
+        dependencies: &[String],
+        f: impl FnOnce() -> Result<(), E> + Send + 'static,
     ) -> RunResult<E> {
         self.check_finished()?;
 
-        if self.handles.len() >= self.max_threads {
+        self.unfinished.insert(name.to_string());
+        self.queue.push_back(Queued {
+            name: name.to_string(),
+            dependencies: dependencies.to_vec(),
+            f: Box::new(f),
+        });
+        self.start_ready();
+
+        // Wait for a thread when tasks are ready, so callers don't queue far ahead of what runs.
+        while self.handles.len() >= self.max_threads && self.has_ready() {
             self.wait_receive_one()?;
         }
 
+        Ok(())
+    }
+
+    fn is_ready(&self, task: &Queued<E>) -> bool {
+        !task
+            .dependencies
+            .iter()
+            .any(|d| self.unfinished.contains(d))
+    }
+
+    fn has_ready(&self) -> bool {
+        self.queue.iter().any(|t| self.is_ready(t))
+    }
+
+    /// Start queued tasks whose dependencies have finished, in the order they were added, and
+    /// skip those with a failed dependency.
+    fn start_ready(&mut self) {
+        let mut i = 0;
+        while i < self.queue.len() {
+            let task = &self.queue[i];
+
+            if let Some(failed) = task.dependencies.iter().find(|d| self.failed.contains(*d)) {
+                let skipped = Failure::Skipped {
+                    dependency: failed.clone(),
+                };
+                let task = self.queue.remove(i).expect("index in queue");
+                self.unfinished.remove(&task.name);
+                self.failed.insert(task.name.clone());
+                self.failures.push((task.name, skipped));
+
+                // Earlier tasks may depend on the skipped one.
+                i = 0;
+                continue;
+            }
+
+            if !self.is_ready(task) || self.handles.len() >= self.max_threads {
+                i += 1;
+                continue;
+            }
+
+            let task = self.queue.remove(i).expect("index in queue");
+            self.spawn(task);
+        }
+    }
+
+    fn spawn(&mut self, task: Queued<E>) {
         let id = (0..self.max_threads)
             .find(|n| !self.handles.contains_key(n))
             .unwrap();
 
         let sender = self.sender.clone();
+        let f = task.f;
         self.handles.insert(
             id,
             spawn(move || {
@@ -67,19 +175,14 @@ impl<E: Send + 'static, P: ProgressListener> ParRunner<E, P> {
             }),
         );
 
-        self.progress.on_start(&name);
-
-        self.names.insert(id, name.to_string());
+        self.progress.on_start(&task.name);
 
-        Ok(())
+        self.names.insert(id, task.name);
     }
 
     fn check_finished(&mut self) -> RunResult<E> {
         while let Ok((id, r)) = self.receiver.try_recv() {
-            let name = self.on_finished(id);
-            if let Err(e) = r {
-                return Err((name, e));
-            }
+            self.on_result(id, r)?;
         }
 
         Ok(())
@@ -87,8 +190,26 @@ impl<E: Send + 'static, P: ProgressListener> ParRunner<E, P> {
 
     fn wait_receive_one(&mut self) -> RunResult<E> {
         let (id, r) = self.receiver.recv().unwrap();
+        self.on_result(id, r)
+    }
+
+    fn on_result(&mut self, id: usize, r: Result<(), E>) -> RunResult<E> {
         let name = self.on_finished(id);
-        r.map_err(|e| (name, e))
+        match r {
+            Ok(()) => {}
+            Err(e) if self.keep_going => {
+                self.failed.insert(name.clone());
+                self.failures.push((name, Failure::Failed(e)));
+            }
+            Err(e) => {
+                // Nothing new starts after a failure, only running tasks are waited for.
+                self.queue.clear();
+                return Err((name, e));
+            }
+        }
+
+        self.start_ready();
+        Ok(())
     }
 
     pub fn into_wait(mut self) -> RunResult<E> {
@@ -97,9 +218,23 @@ impl<E: Send + 'static, P: ProgressListener> ParRunner<E, P> {
         r
     }
 
+    /// Wait for all tasks to finish, returning every failure in the order they happened.
+    pub fn into_failures(mut self) -> Vec<(String, Failure<E>)> {
+        let r = self.wait_receive_all();
+        self.handles.clear();
+
+        let mut failures = std::mem::take(&mut self.failures);
+        if let Err((name, e)) = r {
+            failures.push((name, Failure::Failed(e)));
+        }
+        failures
+    }
+
     fn wait_receive_all(&mut self) -> RunResult<E> {
+        // Queued tasks only wait on tasks added before them, so some task is running while any
+        // are queued.
         loop {
-            if self.handles.len() == 0 {
+            if self.handles.is_empty() {
                 return Ok(());
             }
 
@@ -110,6 +245,7 @@ impl<E: Send + 'static, P: ProgressListener> ParRunner<E, P> {
     fn on_finished(&mut self, id: usize) -> String {
         self.handles.remove(&id);
         let name = self.names.remove(&id).expect("on_finished with missing id");
+        self.unfinished.remove(&name);
         self.progress.on_finish(&name);
         name
     }
@@ -250,6 +386,121 @@ mod tests {
         assert_eq!(*finished.lock().unwrap(), vec![0, 1]);
     }
 
+    #[test]
+    fn waits_for_dependency_to_finish() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener);
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        run_delayed(&mut par_runner, &finished, 10, 0).unwrap();
+
+        let clone = Arc::clone(&finished);
+        par_runner
+            .run_after("task-1", &[String::from("task-0")], move || {
+                clone.lock().unwrap().push(1);
+                Ok(())
+            })
+            .unwrap();
+        drop(par_runner);
+
+        assert_eq!(*finished.lock().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn failed_dependency_returns_err() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener);
+
+        par_runner
+            .run("fails", || {
+                sleep(Duration::from_millis(10));
+                Err(())
+            })
+            .unwrap();
+
+        par_runner
+            .run_after("dependent", &[String::from("fails")], || Ok(()))
+            .unwrap();
+        assert_eq!(par_runner.into_wait(), Err((String::from("fails"), ())));
+    }
+
+    #[test]
+    fn runs_independent_task_while_dependent_waits() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener);
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        run_delayed(&mut par_runner, &finished, 50, 0).unwrap();
+
+        let clone = Arc::clone(&finished);
+        par_runner
+            .run_after("task-1", &[String::from("task-0")], move || {
+                clone.lock().unwrap().push(1);
+                Ok(())
+            })
+            .unwrap();
+        run_delayed(&mut par_runner, &finished, 1, 2).unwrap();
+
+        sleep(Duration::from_millis(20));
+        assert_eq!(*finished.lock().unwrap(), vec![2]);
+
+        drop(par_runner);
+        assert_eq!(*finished.lock().unwrap(), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn keep_going_runs_all_tasks() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener).keep_going(true);
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+
+        par_runner.run("fails", || Err(())).unwrap();
+        run_delayed(&mut par_runner, &finished, 1, 0).unwrap();
+        par_runner.run("fails-too", || Err(())).unwrap();
+
+        assert_eq!(
+            par_runner.into_failures(),
+            vec![
+                (String::from("fails"), Failure::Failed(())),
+                (String::from("fails-too"), Failure::Failed(())),
+            ]
+        );
+        assert_eq!(*finished.lock().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn keep_going_skips_dependents_of_failed_task() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener).keep_going(true);
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+
+        par_runner.run("fails", || Err(())).unwrap();
+        par_runner
+            .run_after("dependent", &[String::from("fails")], || Ok(()))
+            .unwrap();
+        par_runner
+            .run_after("transitive", &[String::from("dependent")], || Ok(()))
+            .unwrap();
+        run_delayed(&mut par_runner, &finished, 1, 0).unwrap();
+
+        assert_eq!(
+            par_runner.into_failures(),
+            vec![
+                (String::from("fails"), Failure::Failed(())),
+                (
+                    String::from("dependent"),
+                    Failure::Skipped {
+                        dependency: String::from("fails")
+                    }
+                ),
+                (
+                    String::from("transitive"),
+                    Failure::Skipped {
+                        dependency: String::from("dependent")
+                    }
+                ),
+            ]
+        );
+        assert_eq!(*finished.lock().unwrap(), vec![0]);
+    }
+
     #[test]
     fn failed_task_into_wait_does_not_wait_for_all_to_finish() {
         let mut par_runner = ParRunner::with_parallel(2, NullProgressListener);
@@ -260,6 +511,6 @@ mod tests {
         par_runner.run("fails", || Err(())).unwrap();
 
         assert_eq!(par_runner.into_wait(), Err((String::from("fails"), ())));
-        assert_eq!(*finished.lock().unwrap(), vec![]);
+        assert_eq!(*finished.lock().unwrap(), Vec::<usize>::new());
     }
 }
@@ -0,0 +1,176 @@
+//! Inspection of the targets Gentle discovers.
+
+use crate::targets::Target;
+use serde::Serialize;
+use std::{collections::BTreeSet, fmt, path::PathBuf, str::FromStr};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct Query {
+    /// Only show targets whose address starts with this prefix.
+    #[structopt(long)]
+    filter: Option<String>,
+
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: Format,
+}
+
+#[derive(Debug)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => anyhow::bail!("Unknown format {s:?}"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TargetInfo {
+    address: String,
+    kind: &'static str,
+    dir: PathBuf,
+    dependencies: BTreeSet<String>,
+    lock_files: BTreeSet<PathBuf>,
+    cache_paths: BTreeSet<PathBuf>,
+    /// `None` when the target does not know its source files, so is never cached.
+    src_files: Option<BTreeSet<PathBuf>>,
+}
+
+impl TargetInfo {
+    fn new(target: &dyn Target) -> anyhow::Result<Self> {
+        let src_files = match target.src_files()? {
+            None => None,
+            Some(selector) => Some(selector.list_allow_empty()?.into_iter().collect()),
+        };
+
+        Ok(TargetInfo {
+            address: target.to_string(),
+            kind: target.kind(),
+            dir: target.dir().to_path_buf(),
+            dependencies: target.dependencies()?.into_iter().collect(),
+            lock_files: target.lock_files().into_iter().collect(),
+            cache_paths: target.cache_paths().into_iter().collect(),
+            src_files,
+        })
+    }
+}
+
+impl fmt::Display for TargetInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.address)?;
+        writeln!(f, "  kind: {}", self.kind)?;
+        writeln!(f, "  dir: {}", self.dir.display())?;
+
+        write_list(f, "dependencies", self.dependencies.iter())?;
+        write_list(f, "lock_files", self.lock_files.iter().map(|p| p.display()))?;
+        write_list(
+            f,
+            "cache_paths",
+            self.cache_paths.iter().map(|p| p.display()),
+        )?;
+        match &self.src_files {
+            None => writeln!(f, "  src_files: unknown"),
+            Some(files) => write_list(f, "src_files", files.iter().map(|p| p.display())),
+        }
+    }
+}
+
+fn write_list(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    items: impl ExactSizeIterator<Item = impl fmt::Display>,
+) -> fmt::Result {
+    if items.len() == 0 {
+        return writeln!(f, "  {name}: none");
+    }
+
+    writeln!(f, "  {name}:")?;
+    for item in items {
+        writeln!(f, "    {item}")?;
+    }
+    Ok(())
+}
+
+pub fn run(config: &crate::Config, query: Query) -> anyhow::Result<()> {
+    let mut infos = Vec::new();
+    for target in crate::targets::targets(config)? {
+        let address = target.to_string();
+        if let Some(filter) = &query.filter {
+            if !address.starts_with(filter) {
+                continue;
+            }
+        }
+
+        match TargetInfo::new(&*target) {
+            Ok(info) => infos.push(info),
+            Err(e) => eprintln!("Warning: skipping {address}: {e:#}"),
+        }
+    }
+    infos.sort_by(|a, b| a.address.cmp(&b.address));
+
+    match query.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&infos)?),
+        Format::Text => {
+            for info in infos {
+                print!("{info}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(src_files: Option<BTreeSet<PathBuf>>) -> TargetInfo {
+        TargetInfo {
+            address: "//app:go".into(),
+            kind: "go",
+            dir: "app".into(),
+            dependencies: ["//lib:go".to_string()].into(),
+            lock_files: ["app/go.sum".into()].into(),
+            cache_paths: BTreeSet::new(),
+            src_files,
+        }
+    }
+
+    #[test]
+    fn text_lists_every_field() {
+        assert_eq!(
+            info(Some(["app/main.go".into()].into())).to_string(),
+            "\
+//app:go
+  kind: go
+  dir: app
+  dependencies:
+    //lib:go
+  lock_files:
+    app/go.sum
+  cache_paths: none
+  src_files:
+    app/main.go
+"
+        );
+        assert!(info(None).to_string().ends_with("  src_files: unknown\n"));
+    }
+
+    #[test]
+    fn json_shows_unknown_src_files_as_null() {
+        let json = serde_json::to_value(info(None)).unwrap();
+        assert_eq!(json["address"], "//app:go");
+        assert_eq!(json["dependencies"], serde_json::json!(["//lib:go"]));
+        assert_eq!(json["cache_paths"], serde_json::json!([]));
+        assert!(json["src_files"].is_null());
+    }
+}
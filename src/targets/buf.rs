@@ -0,0 +1,273 @@
+use super::*;
+
+use crate::git;
+use anyhow::Context;
+use serde::Deserialize;
+
+/// Files that configure a buf module, tracked as sources alongside its `.proto` files.
+const CONFIG_FILES: [&str; 4] = ["buf.yaml", "buf.lock", "buf.gen.yaml", "buf.work.yaml"];
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+fn discover(path: &Path, config: &Config) -> anyhow::Result<Targets> {
+    if !is_module(path, Path::new("."))? {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Box::new(BufModuleTarget {
+        path: path.into(),
+        against: config.buf.against.clone(),
+    })])
+}
+
+/// Whether `path` has a buf.yaml, or is `root` with .proto files below it that aren't part of a
+/// module with a buf.yaml.
+///
+/// Like buf without configuration, `root` is then the module's root, so packages like `api.v1`
+/// are in `api/v1` and imported as `api/v1/...`.
+fn is_module(path: &Path, root: &Path) -> anyhow::Result<bool> {
+    if path.join("buf.yaml").try_exists()? {
+        return Ok(true);
+    }
+    if simplify(path)? != simplify(root)? {
+        return Ok(false);
+    }
+
+    Ok(!module_files(path)?.protos.is_empty())
+}
+
+/// The files of the module rooted at `dir`, which leave out other modules below it.
+#[derive(Default)]
+struct ModuleFiles {
+    protos: Vec<PathBuf>,
+    /// Directories of modules with their own buf.yaml below `dir`.
+    nested_modules: Vec<PathBuf>,
+}
+
+fn module_files(dir: &Path) -> anyhow::Result<ModuleFiles> {
+    let mut result = ModuleFiles::default();
+
+    // Walked like directories are during discovery.
+    for entry in ignore::Walk::new(dir) {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            if entry.depth() > 0 && path.join("buf.yaml").try_exists()? {
+                result.nested_modules.push(path.to_path_buf());
+            }
+        } else if path.extension().is_some_and(|ext| ext == "proto") {
+            result.protos.push(path.to_path_buf());
+        }
+    }
+    // Files of nested modules are walked too, and dropped here.
+    result
+        .protos
+        .retain(|p| !result.nested_modules.iter().any(|m| p.starts_with(m)));
+
+    result.protos.sort();
+    result.nested_modules.sort();
+    Ok(result)
+}
+
+/// Options for buf targets, from the `[buf]` section of `gentle.toml`.
+#[derive(Deserialize, Default, Debug)]
+pub struct BufConfig {
+    /// Git ref to check for breaking changes against when linting, like `origin/main`.
+    against: Option<String>,
+}
+
+/// A buf module, or the `.proto` files outside of those checked with buf's defaults.
+pub struct BufModuleTarget {
+    path: PathBuf,
+    against: Option<String>,
+}
+
+impl BufModuleTarget {
+    /// The `--against` input for `buf breaking`, or `None` if the module doesn't exist at
+    /// `commit` yet, so it can't have broken anything.
+    fn against_input(&self, commit: &str) -> anyhow::Result<Option<String>> {
+        let subdir = git::prefix()?.join(simplify(&self.path)?);
+        let subdir = subdir.to_string_lossy();
+        if !git::exists_at(commit, &subdir)? {
+            return Ok(None);
+        }
+
+        // In worktrees and submodules `.git` is a file, so the shared git directory is used.
+        let mut input = format!("{}#ref={commit}", git::common_dir()?.display());
+        if !subdir.is_empty() {
+            input.push_str(&format!(",subdir={subdir}"));
+        }
+        Ok(Some(input))
+    }
+
+    fn against_commit(&self) -> anyhow::Result<Option<String>> {
+        self.against
+            .as_deref()
+            .map(|rev| git::resolve(rev).context(format!("Resolving buf against ref {rev:?}")))
+            .transpose()
+    }
+
+    /// Command running buf on the module, leaving out modules nested in it.
+    fn buf(&self, args: &[&str]) -> anyhow::Result<Command> {
+        let mut command = Command::new("buf");
+        command.args(args).arg(&self.path);
+        for module in module_files(&self.path)?.nested_modules {
+            command.arg("--exclude-path").arg(module);
+        }
+        Ok(command)
+    }
+}
+
+fn run(command: &mut Command) -> anyhow::Result<()> {
+    command
+        .output()
+        .context("Running buf")?
+        .success_ok()
+        .map(|_| ())
+        .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
+}
+
+impl Display for BufModuleTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_address(f, &self.path, self.kind())
+    }
+}
+
+impl Target for BufModuleTarget {
+    fn kind(&self) -> &'static str {
+        "buf_module"
+    }
+
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self) -> anyhow::Result<()> {
+        // Compiles the module without writing an image.
+        run(&mut self.buf(&["build"])?)
+    }
+
+    fn perform_lint(&self, _: Mode) -> anyhow::Result<()> {
+        // buf has no lint fixes to apply.
+        run(&mut self.buf(&["lint"])?)?;
+
+        let commit = match self.against_commit() {
+            Ok(Some(commit)) => commit,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                // Like in shallow clones, which don't have the ref.
+                eprintln!("Warning: skipping buf breaking for {self}: {e:#}");
+                return Ok(());
+            }
+        };
+        let Some(against) = self.against_input(&commit)? else {
+            return Ok(());
+        };
+        run(self.buf(&["breaking"])?.args(["--against", &against]))
+    }
+
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()> {
+        let args: &[&str] = match mode {
+            Mode::Check => &["--diff", "--exit-code"],
+            Mode::Fix => &["--write"],
+        };
+        run(self.buf(&["format"])?.args(args))
+    }
+
+    fn perform_build(&self, build: &Build) -> anyhow::Result<()> {
+        let template = self.path.join("buf.gen.yaml");
+        if !template.try_exists()? {
+            return Ok(());
+        }
+
+        // Output paths in the template are relative to the module's directory in the build
+        // output, so modules don't overwrite each other's generated code.
+        run(self
+            .buf(&["generate"])?
+            .arg("--template")
+            .arg(template)
+            .arg("--output")
+            .arg(build.out.join(simplify(&self.path)?)))
+    }
+
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        Ok(vec![tool_version("buf", &["--version"])?])
+    }
+
+    fn lint_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        // Without the commit, linting skips buf breaking.
+        Ok(self.against_commit().ok().flatten().into_iter().collect())
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        let cache_dir = std::env::var_os("BUF_CACHE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                Path::new(&std::env::var("HOME").unwrap_or(String::from("/"))).join(".cache/buf")
+            });
+
+        [cache_dir].into_iter().collect()
+    }
+
+    fn lock_files(&self) -> HashSet<PathBuf> {
+        [self.path.join("buf.lock")]
+            .into_iter()
+            .filter(|f| f.exists())
+            .collect()
+    }
+
+    fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
+        let config = CONFIG_FILES.iter().map(|f| self.path.join(f));
+        let builder = config
+            .chain(module_files(&self.path)?.protos)
+            .fold(FileSelector::builder(), |b, f| b.path(f));
+        Ok(Some(builder.build()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(root: &Path, files: &[&str]) {
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+    }
+
+    #[test]
+    fn root_is_module_of_loose_protos() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        create(root, &["api/v1/api.proto", "api/v1/types/types.proto"]);
+        let is_module = |dir: &str| is_module(&root.join(dir), root).unwrap();
+
+        assert!(is_module(""));
+        assert!(!is_module("api"));
+        assert!(!is_module("api/v1"));
+
+        create(root, &["api/buf.yaml"]);
+        assert!(is_module("api"));
+        assert!(!is_module(""));
+    }
+
+    #[test]
+    fn nested_modules_are_left_out() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        create(
+            root,
+            &[
+                "api/v1/api.proto",
+                "vendor/buf.yaml",
+                "vendor/google/type/date.proto",
+                "docs/readme.md",
+            ],
+        );
+
+        let files = module_files(root).unwrap();
+        assert_eq!(files.protos, [root.join("api/v1/api.proto")]);
+        assert_eq!(files.nested_modules, [root.join("vendor")]);
+    }
+}
@@ -1,85 +1,373 @@
 use super::*;
 
+use anyhow::Context;
+use dockerfile::DockerIgnore;
+use engine::Engine;
+use serde::Deserialize;
+
+mod dockerfile;
+mod engine;
+
 #[linkme::distributed_slice(TARGET_DISCOVERY)]
-fn discover(path: &Path) -> anyhow::Result<Vec<Box<dyn Target>>> {
-    if path.join("Dockerfile").try_exists()? {
-        Ok(vec![(Box::new(DockerfileTarget::new(&path)))])
-    } else {
-        Ok(Vec::new())
+fn discover(path: &Path, config: &Config) -> anyhow::Result<Targets> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let is_dockerfile = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| variant(name).is_some());
+        if is_dockerfile && entry.file_type()?.is_file() {
+            files.push(entry.file_name());
+        }
     }
+    files.sort();
+
+    let mut result: Targets = Vec::new();
+    for file in files {
+        let mut target = DockerfileTarget::new(path, Path::new(&file));
+        target.engine = config.docker.engine;
+        if let Some(image) = config.docker.images.get(&target.to_string()) {
+            target.config = image.clone();
+        }
+        result.push(Box::new(target));
+    }
+    Ok(result)
+}
+
+/// Variant of a Dockerfile, like `dev` for `Dockerfile.dev` or `dev.Dockerfile`.
+///
+/// `Some("")` for a plain `Dockerfile`, `None` for files that are not Dockerfiles.
+fn variant(file_name: &str) -> Option<&str> {
+    if file_name == "Dockerfile" {
+        return Some("");
+    }
+    if file_name.ends_with(".dockerignore") {
+        return None;
+    }
+
+    file_name
+        .strip_prefix("Dockerfile.")
+        .or_else(|| file_name.strip_suffix(".Dockerfile"))
+        .filter(|v| !v.is_empty())
+}
+
+/// Options for Docker targets, from the `[docker]` section of `gentle.toml`.
+#[derive(Deserialize, Default, Debug)]
+pub struct DockerConfig {
+    /// Engine to build images with, detected from those installed by default.
+    engine: Option<Engine>,
+
+    /// Options for individual images, keyed by target address.
+    #[serde(default)]
+    images: BTreeMap<String, ImageConfig>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+struct ImageConfig {
+    /// Image name to tag builds with, defaults to one derived from the target's directory.
+    name: Option<String>,
+
+    /// Build stage to stop at, passed as `--target`.
+    target: Option<String>,
+
+    #[serde(default)]
+    build_args: BTreeMap<String, String>,
+
+    /// Write the image to a `docker save` tarball in the build output directory.
+    #[serde(default)]
+    save: bool,
 }
 
 pub struct DockerfileTarget {
     path: PathBuf,
+    /// Name of the Dockerfile within `path`.
+    file: PathBuf,
+    /// See [`variant`].
+    variant: String,
+    engine: Option<Engine>,
+    config: ImageConfig,
 }
 
 impl DockerfileTarget {
-    pub fn new(path: &Path) -> Self {
+    pub fn new(path: &Path, file: &Path) -> Self {
         Self {
             path: path.to_path_buf(),
+            file: file.to_path_buf(),
+            variant: variant(&file.to_string_lossy())
+                .unwrap_or_default()
+                .to_string(),
+            engine: None,
+            config: ImageConfig::default(),
+        }
+    }
+
+    fn engine(&self) -> anyhow::Result<Engine> {
+        self.engine.map(Ok).unwrap_or_else(Engine::detect)
+    }
+
+    fn dockerfile(&self) -> PathBuf {
+        self.path.join(&self.file)
+    }
+
+    fn image_name(&self) -> String {
+        self.config.name.clone().unwrap_or_else(|| {
+            let dir = simplify(&self.path).unwrap_or_else(|_| self.path.clone());
+            let name = default_image_name(&dir);
+            match self.variant.as_str() {
+                "" => name,
+                variant => format!("{name}-{}", default_image_name(Path::new(variant))),
+            }
+        })
+    }
+
+    /// Tag for the image, identifying its sources and build options.
+    fn image_tag(&self) -> anyhow::Result<String> {
+        let files = self
+            .src_files()?
+            .expect("implemented for DockerfileTarget")
+            .list()?;
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(crate::hash_files::hash_files(files)?.as_bytes());
+        hasher.update(self.config.target.as_deref().unwrap_or_default().as_bytes());
+        for (name, value) in &self.config.build_args {
+            hasher.update(format!("\0{name}={value}").as_bytes());
+        }
+
+        let hash = hasher.finalize().to_hex();
+        Ok(format!("{}:{}", self.image_name(), &hash[..12]))
+    }
+
+    /// The `.dockerignore` file for this target's build and its rules.
+    ///
+    /// Like BuildKit, prefers an ignore file specific to the Dockerfile over the one at the root
+    /// of the context.
+    fn dockerignore(&self) -> anyhow::Result<(PathBuf, DockerIgnore)> {
+        let specific = self
+            .path
+            .join(format!("{}.dockerignore", self.file.display()));
+        let path = if specific.try_exists()? {
+            specific
+        } else {
+            PathBuf::from(".dockerignore")
+        };
+
+        let ignore = match std::fs::read_to_string(&path) {
+            Ok(contents) => DockerIgnore::parse(&contents).context(format!("Parsing {path:?}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DockerIgnore::parse("")?,
+            Err(e) => return Err(e).context(format!("Reading {path:?}")),
+        };
+        Ok((path, ignore))
+    }
+
+    /// The nearest hadolint config file in the Dockerfile's directory or its parents.
+    fn hadolint_config(&self) -> anyhow::Result<Option<PathBuf>> {
+        for dir in self.path.ancestors() {
+            for name in [".hadolint.yaml", ".hadolint.yml"] {
+                let path = dir.join(name);
+                if path.try_exists()? {
+                    return Ok(Some(path));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Builds the image from the repository root and returns its tag.
+    fn build_image(&self) -> anyhow::Result<String> {
+        let engine = self.engine()?;
+        let tag = self.image_tag()?;
+
+        let mut command = engine.build();
+        command
+            .arg(format!("--file={}", self.dockerfile().display()))
+            .arg(format!("--tag={tag}"));
+        if let Some(stage) = &self.config.target {
+            command.arg(format!("--target={stage}"));
+        }
+        for (name, value) in &self.config.build_args {
+            command.arg(format!("--build-arg={name}={value}"));
         }
+
+        command
+            .arg(".")
+            .output()
+            .context(format!("Running {} build", engine.program()))?
+            .success_ok()
+            .map_err(|out| anyhow::anyhow!("{}", out.stderr))?;
+
+        Ok(tag)
+    }
+}
+
+/// Image name for a Dockerfile in `dir`, restricted to the characters Docker allows.
+fn default_image_name(dir: &Path) -> String {
+    let name = dir
+        .components()
+        .map(|c| {
+            c.as_os_str()
+                .to_string_lossy()
+                .to_lowercase()
+                .replace(
+                    |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '_',
+                    "-",
+                )
+                .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+                .to_string()
+        })
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    if name.is_empty() {
+        String::from("gentle")
+    } else {
+        name
     }
 }
 
 impl Display for DockerfileTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO(shelbyd): De-duplicate formatting of target addresses.
-        let package = self.path.display().to_string().replacen("./", "", 1);
-        write!(f, "//{package}:docker_image")
+        match self.variant.as_str() {
+            "" => fmt_address(f, &self.path, self.kind()),
+            variant => fmt_address(f, &self.path, &format!("{}.{variant}", self.kind())),
+        }
     }
 }
 
 impl Target for DockerfileTarget {
-    fn perform_lint(&self) -> anyhow::Result<()> {
-        Ok(())
+    fn kind(&self) -> &'static str {
+        "docker_image"
     }
 
-    fn perform_format(&self) -> anyhow::Result<()> {
-        Ok(())
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_lint(&self, _: Mode) -> anyhow::Result<()> {
+        let mut command = Command::new("hadolint");
+        if let Some(config) = self.hadolint_config()? {
+            command.arg("--config").arg(config);
+        }
+
+        // hadolint has no fixes to apply.
+        command
+            .arg(self.dockerfile())
+            .output()
+            .context("Running hadolint")?
+            .success_ok()
+            .map(|_| ())
+            .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
     }
 
-    fn perform_build(&self, _: &Build) -> anyhow::Result<()> {
+    fn perform_format(&self, _: Mode) -> anyhow::Result<()> {
         Ok(())
     }
 
-    fn perform_test(&self) -> anyhow::Result<()> {
-        Command::new("docker")
-            .args([
-                "build",
-                &format!("--file={}", self.path.join("Dockerfile").display()),
-                ".",
-            ])
-            .output()?
+    fn perform_build(&self, build: &Build) -> anyhow::Result<()> {
+        let tag = self.build_image()?;
+        if !self.config.save {
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(&build.out)?;
+        let file = format!("{}.tar", self.image_name().replace('/', "-"));
+        let engine = self.engine()?;
+        engine
+            .save(&tag, &build.out.join(file))
+            .output()
+            .context(format!("Saving image with {}", engine.program()))?
             .success_ok()
             .map(|_| ())
             .map_err(|out| anyhow::anyhow!("{}", out.stderr))
     }
 
+    fn caches_build(&self) -> bool {
+        // Only a saved tarball can be restored, an image left in the engine may be gone.
+        self.config.save
+    }
+
+    fn perform_test(&self) -> anyhow::Result<()> {
+        self.build_image().map(|_| ())
+    }
+
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        // Lint and format don't need an engine, building reports it missing.
+        let version = self
+            .engine()
+            .and_then(|engine| tool_version(engine.program(), &["--version"]));
+        Ok(version.into_iter().collect())
+    }
+
     fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
-        let dockerfile = self.path.join("Dockerfile");
-        let mut builder = FileSelector::builder().path(&dockerfile);
-
-        let docker_contents = std::fs::read_to_string(&dockerfile)?;
-        for line in docker_contents.lines() {
-            let line = line.trim();
-            let mut args = match line.strip_prefix("COPY ").or(line.strip_prefix("ADD ")) {
-                Some(suf) => suf,
-                None => continue,
+        let dockerfile = self.dockerfile();
+        let contents =
+            std::fs::read_to_string(&dockerfile).context(format!("Reading {dockerfile:?}"))?;
+
+        let mut sources = FileSelector::builder().path(&dockerfile);
+        for source in dockerfile::context_sources(&contents, &self.config.build_args) {
+            if Path::new(&source)
+                .components()
+                .any(|c| c == Component::ParentDir)
+            {
+                continue;
             }
-            .split(' ')
-            .filter(|s| !s.is_empty())
-            .collect::<Vec<_>>();
 
-            let _dest = args.pop().ok_or(anyhow::anyhow!("COPY with no args"))?;
+            sources = if source.contains(['*', '?', '[']) {
+                // Directories matching the pattern are copied with their contents.
+                sources.glob(&source)?.glob(&format!("{source}/**"))?
+            } else {
+                sources.path(&source)
+            };
+        }
 
-            for arg in args {
-                // TODO(shelbyd): Should this find targets for src files in these directories?
-                //                Alternatively, incorporate .dockerignore.
-                //                Or could: https://snippets.khromov.se/see-which-files-are-included-in-your-docker-build-context/
-                builder = builder.path(arg);
+        let (ignore_file, ignore) = self.dockerignore()?;
+        let mut builder = FileSelector::builder().path(&dockerfile).path(ignore_file);
+        if let Some(config) = self.hadolint_config()? {
+            builder = builder.path(config);
+        }
+        for path in sources.build().list()? {
+            let in_context = path.strip_prefix("./").unwrap_or(&path);
+            if !ignore.is_ignored(in_context) {
+                builder = builder.path(path);
             }
         }
 
         Ok(Some(builder.build()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dockerfile_variants() {
+        assert_eq!(variant("Dockerfile"), Some(""));
+        assert_eq!(variant("Dockerfile.dev"), Some("dev"));
+        assert_eq!(variant("release.Dockerfile"), Some("release"));
+        assert_eq!(variant("Dockerfile.dockerignore"), None);
+        assert_eq!(variant("Dockerfile."), None);
+        assert_eq!(variant("Makefile"), None);
+    }
+
+    #[test]
+    fn variant_addresses() {
+        let plain = DockerfileTarget::new(Path::new("./svc"), Path::new("Dockerfile"));
+        let dev = DockerfileTarget::new(Path::new("./svc"), Path::new("Dockerfile.dev"));
+
+        assert_eq!(plain.to_string(), "//svc:docker_image");
+        assert_eq!(dev.to_string(), "//svc:docker_image.dev");
+        assert_eq!(dev.image_name(), "svc-dev");
+    }
+
+    #[test]
+    fn image_name_from_dir() {
+        assert_eq!(default_image_name(Path::new("svc/My_API")), "svc/my_api");
+        assert_eq!(default_image_name(Path::new("svc/web app")), "svc/web-app");
+        assert_eq!(default_image_name(Path::new("_tools")), "tools");
+        assert_eq!(default_image_name(Path::new("")), "gentle");
+    }
+}
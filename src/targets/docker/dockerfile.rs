@@ -0,0 +1,487 @@
+//! Just enough of a Dockerfile parser to find the files a build reads from its context.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+struct Instruction {
+    /// Uppercased instruction, like `COPY`.
+    keyword: String,
+    args: String,
+}
+
+/// Sources of `COPY` and `ADD` instructions that come from the build context, relative to it.
+///
+/// Variables from `ARG` and `ENV` are substituted, with `build_args` overriding `ARG` defaults.
+/// Copies from other stages or images, heredocs and remote URLs are skipped.
+pub fn context_sources(contents: &str, build_args: &BTreeMap<String, String>) -> Vec<String> {
+    let escape = escape_directive(contents);
+
+    let mut global_args = HashMap::new();
+    let mut vars = HashMap::new();
+    let mut in_stage = false;
+    let mut result = Vec::new();
+
+    for instruction in instructions(contents, escape) {
+        match instruction.keyword.as_str() {
+            "FROM" => {
+                in_stage = true;
+                vars.clear();
+            }
+            "ARG" => {
+                let scope = if in_stage { &vars } else { &global_args };
+                let mut declared = Vec::new();
+                for word in split_words(&instruction.args) {
+                    let (name, default) = match word.split_once('=') {
+                        Some((name, default)) => (name, Some(substitute(default, scope, escape))),
+                        None => (word.as_str(), None),
+                    };
+                    let inherited = in_stage.then(|| global_args.get(name).cloned()).flatten();
+                    let value = build_args.get(name).cloned().or(default).or(inherited);
+                    declared.extend(value.map(|v| (name.to_string(), v)));
+                }
+
+                let scope = if in_stage {
+                    &mut vars
+                } else {
+                    &mut global_args
+                };
+                scope.extend(declared);
+            }
+            "ENV" if in_stage => {
+                let args = substitute(&instruction.args, &vars, escape);
+                let words = split_words(&args);
+                match words.first() {
+                    Some(first) if !first.contains('=') => {
+                        let value = args.trim_start()[first.len()..].trim().to_string();
+                        vars.insert(first.clone(), value);
+                    }
+                    _ => {
+                        for word in words {
+                            if let Some((name, value)) = word.split_once('=') {
+                                vars.insert(name.to_string(), value.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            "COPY" | "ADD" => {
+                let remote = |s: &str| s.contains("://") || s.starts_with("git@");
+                result.extend(
+                    copy_sources(&instruction.args)
+                        .into_iter()
+                        .map(|s| substitute(&s, &vars, escape))
+                        .filter(|s| !(instruction.keyword == "ADD" && remote(s)))
+                        .map(|s| match s.trim_start_matches('/') {
+                            "" => String::from("."),
+                            s => s.to_string(),
+                        }),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
+
+/// Sources of a `COPY` or `ADD`, without substitution.
+fn copy_sources(args: &str) -> Vec<String> {
+    let mut rest = args.trim();
+    while rest.starts_with("--") {
+        let (flag, remaining) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        if flag.starts_with("--from=") {
+            return Vec::new();
+        }
+        rest = remaining.trim_start();
+    }
+
+    let mut words = if rest.starts_with('[') {
+        serde_json::from_str::<Vec<String>>(rest).unwrap_or_default()
+    } else {
+        rest.split_whitespace().map(String::from).collect()
+    };
+
+    // The last word is the destination.
+    words.pop();
+    words.retain(|w| !w.starts_with("<<"));
+    words
+}
+
+/// The escape character set by a `# escape=` parser directive, `\` by default.
+fn escape_directive(contents: &str) -> char {
+    for line in contents.lines() {
+        let Some(directive) = line.trim().strip_prefix('#') else {
+            break;
+        };
+        let Some((name, value)) = directive.split_once('=') else {
+            break;
+        };
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "escape" => return value.trim().chars().next().unwrap_or('\\'),
+            "syntax" | "check" => continue,
+            _ => break,
+        }
+    }
+
+    '\\'
+}
+
+/// Instructions with line continuations joined, and comments and heredoc bodies removed.
+fn instructions(contents: &str, escape: char) -> Vec<Instruction> {
+    let mut lines = contents.lines();
+    let mut result = Vec::new();
+
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut logical = String::new();
+        let mut current = Some(line);
+        while let Some(line) = current.take() {
+            let Some(continued) = line.strip_suffix(escape) else {
+                logical.push_str(line);
+                break;
+            };
+            logical.push_str(continued);
+            logical.push(' ');
+
+            current = lines
+                .by_ref()
+                .map(str::trim)
+                .find(|l| !l.is_empty() && !l.starts_with('#'));
+        }
+
+        let (keyword, args) = logical
+            .split_once(char::is_whitespace)
+            .unwrap_or((&logical, ""));
+
+        for (terminator, strip_tabs) in heredoc_markers(args) {
+            for line in lines.by_ref() {
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line
+                };
+                if line == terminator {
+                    break;
+                }
+            }
+        }
+
+        result.push(Instruction {
+            keyword: keyword.to_ascii_uppercase(),
+            args: args.trim().to_string(),
+        });
+    }
+
+    result
+}
+
+/// Terminators of heredocs started in `args`, and whether their lines have leading tabs removed.
+fn heredoc_markers(args: &str) -> Vec<(String, bool)> {
+    args.split_whitespace()
+        .filter_map(|word| {
+            let marker = word.strip_prefix("<<")?;
+            let (marker, strip_tabs) = match marker.strip_prefix('-') {
+                Some(marker) => (marker, true),
+                None => (marker, false),
+            };
+
+            let terminator = marker.trim_matches(|c| c == '"' || c == '\'');
+            let valid = !terminator.is_empty()
+                && terminator
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            valid.then(|| (terminator.to_string(), strip_tabs))
+        })
+        .collect()
+}
+
+/// Splits on whitespace outside of quotes, removing the quotes.
+fn split_words(s: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    result.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        result.push(current);
+    }
+
+    result
+}
+
+/// Replaces `$NAME`, `${NAME}`, `${NAME:-default}` and `${NAME:+alternative}` with their values.
+fn substitute(word: &str, vars: &HashMap<String, String>, escape: char) -> String {
+    let mut result = String::new();
+    let mut chars = word.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == escape && chars.peek() == Some(&'$') {
+            result.push('$');
+            chars.next();
+            continue;
+        }
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            let expression = chars.by_ref().take_while(|&c| c != '}').collect::<String>();
+            let (name, modifier) = match expression.find([':', '-', '+']) {
+                Some(i) => expression.split_at(i),
+                None => (expression.as_str(), ""),
+            };
+            let value = vars.get(name).filter(|v| !v.is_empty());
+
+            let modifier = modifier.trim_start_matches(':');
+            if let Some(default) = modifier.strip_prefix('-') {
+                result.push_str(value.map_or(default, |v| v.as_str()));
+            } else if let Some(alternative) = modifier.strip_prefix('+') {
+                result.push_str(value.map_or("", |_| alternative));
+            } else {
+                result.push_str(value.map_or("", |v| v.as_str()));
+            }
+        } else if chars
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphabetic() || *c == '_')
+        {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            result.push_str(vars.get(&name).map_or("", |v| v.as_str()));
+        } else {
+            result.push('$');
+        }
+    }
+
+    result
+}
+
+/// Exclusion rules from a `.dockerignore` file.
+pub struct DockerIgnore {
+    /// Patterns in order, with whether they exclude or re-include matching paths.
+    rules: Vec<(glob::Pattern, bool)>,
+}
+
+impl DockerIgnore {
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let mut rules = Vec::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (pattern, exclude) = match line.strip_prefix('!') {
+                Some(pattern) => (pattern.trim(), false),
+                None => (line, true),
+            };
+            let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+            let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+            rules.push((glob::Pattern::new(pattern)?, exclude));
+        }
+
+        Ok(DockerIgnore { rules })
+    }
+
+    /// Whether `path`, relative to the build context, is left out of the context.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+
+        let mut ignored = false;
+        for (pattern, exclude) in &self.rules {
+            // Excluding a directory excludes everything in it.
+            let matches = path
+                .ancestors()
+                .filter(|a| !a.as_os_str().is_empty())
+                .any(|a| pattern.matches_path_with(a, options));
+            if matches {
+                ignored = *exclude;
+            }
+        }
+
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(contents: &str) -> Vec<String> {
+        context_sources(contents, &BTreeMap::new())
+    }
+
+    #[test]
+    fn copy_and_add() {
+        let contents = "\
+FROM alpine
+COPY a.txt b/ /app/
+add c.txt /app/
+";
+
+        assert_eq!(sources(contents), ["a.txt", "b/", "c.txt"]);
+    }
+
+    #[test]
+    fn flags() {
+        let contents = "\
+FROM rust AS build
+COPY --chown=app:app --chmod=644 src /src
+FROM alpine
+COPY --from=build /target/app /app
+COPY --link=true ./run.sh /run.sh
+";
+
+        assert_eq!(sources(contents), ["src", "./run.sh"]);
+    }
+
+    #[test]
+    fn json_form() {
+        let contents = r#"
+FROM alpine
+COPY ["with space.txt", "other.txt", "/app/"]
+"#;
+
+        assert_eq!(sources(contents), ["with space.txt", "other.txt"]);
+    }
+
+    #[test]
+    fn continuations_and_comments() {
+        let contents = "\
+FROM alpine
+COPY a.txt \\
+# A comment in the middle.
+
+     b.txt \\
+     /app/
+";
+
+        assert_eq!(sources(contents), ["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn escape_directive_changes_continuation() {
+        let contents = "\
+# escape=`
+FROM mcr.microsoft.com/windows
+COPY a.txt `
+     C:\\app\\
+";
+
+        assert_eq!(sources(contents), ["a.txt"]);
+    }
+
+    #[test]
+    fn heredocs() {
+        let contents = "\
+FROM alpine
+RUN <<EOF
+COPY not-an-instruction.txt /
+EOF
+COPY <<-EOT a.txt /app/
+\tcontents
+\tEOT
+";
+
+        assert_eq!(sources(contents), ["a.txt"]);
+    }
+
+    #[test]
+    fn arg_and_env_substitution() {
+        let contents = "\
+ARG APP=web
+ARG VERSION=1
+FROM alpine
+ARG APP
+ARG CONFIG=${APP}.toml
+ENV DIR=services/$APP
+COPY ${DIR}/bin config/${CONFIG} ${MISSING:-default.txt} $VERSION.txt /app/
+";
+
+        assert_eq!(
+            sources(contents),
+            ["services/web/bin", "config/web.toml", "default.txt", ".txt"]
+        );
+    }
+
+    #[test]
+    fn build_args_override_defaults() {
+        let contents = "\
+FROM alpine
+ARG APP=web
+COPY $APP /app
+";
+        let build_args = [(String::from("APP"), String::from("api"))].into();
+
+        assert_eq!(context_sources(contents, &build_args), ["api"]);
+    }
+
+    #[test]
+    fn skips_remote_sources() {
+        let contents = "\
+FROM alpine
+ADD https://example.com/file.tar.gz local.tar.gz /app/
+COPY / /app/
+";
+
+        assert_eq!(sources(contents), ["local.tar.gz", "."]);
+    }
+
+    #[test]
+    fn dockerignore() {
+        let ignore = DockerIgnore::parse(
+            "\
+# Comment
+/target
+**/*.log
+docs
+!docs/README.md
+",
+        )
+        .unwrap();
+
+        assert!(ignore.is_ignored(Path::new("target/debug/app")));
+        assert!(ignore.is_ignored(Path::new("svc/logs/out.log")));
+        assert!(ignore.is_ignored(Path::new("docs/guide.md")));
+        assert!(!ignore.is_ignored(Path::new("docs/README.md")));
+        assert!(!ignore.is_ignored(Path::new("src/main.rs")));
+    }
+}
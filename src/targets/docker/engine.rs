@@ -0,0 +1,89 @@
+//! Container engines that can build images from Dockerfiles.
+
+use crate::targets::on_path;
+use serde::Deserialize;
+use std::{path::Path, process::Command};
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    Docker,
+    Podman,
+    Buildah,
+}
+
+impl Engine {
+    const ALL: [Engine; 3] = [Engine::Docker, Engine::Podman, Engine::Buildah];
+
+    /// The first engine installed, preferring Docker.
+    pub fn detect() -> anyhow::Result<Engine> {
+        Engine::ALL
+            .into_iter()
+            .find(|e| on_path(e.program()))
+            .ok_or_else(|| anyhow::anyhow!("Found none of docker, podman or buildah on PATH"))
+    }
+
+    pub fn program(self) -> &'static str {
+        match self {
+            Engine::Docker => "docker",
+            Engine::Podman => "podman",
+            Engine::Buildah => "buildah",
+        }
+    }
+
+    /// Command to build an image, to be completed with `--file`, `--tag` and similar flags, which
+    /// all engines accept.
+    pub fn build(self) -> Command {
+        let mut command = Command::new(self.program());
+        command.arg("build");
+        command
+    }
+
+    /// Command to write the image `tag` to a tarball at `path`, loadable with `docker load`.
+    pub fn save(self, tag: &str, path: &Path) -> Command {
+        let mut command = Command::new(self.program());
+        match self {
+            Engine::Docker | Engine::Podman => {
+                command.args(["save", "--output"]).arg(path).arg(tag);
+            }
+            Engine::Buildah => {
+                command
+                    .arg("push")
+                    .arg(tag)
+                    .arg(format!("docker-archive:{}:{tag}", path.display()));
+            }
+        }
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|a| a.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn save_commands() {
+        let path = Path::new("out/app.tar");
+
+        assert_eq!(
+            args(&Engine::Podman.save("app:1", path)),
+            ["podman", "save", "--output", "out/app.tar", "app:1"]
+        );
+        assert_eq!(
+            args(&Engine::Buildah.save("app:1", path)),
+            [
+                "buildah",
+                "push",
+                "app:1",
+                "docker-archive:out/app.tar:app:1"
+            ]
+        );
+    }
+}
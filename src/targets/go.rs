@@ -1,102 +1,294 @@
 use super::*;
 
 use anyhow::Context;
+use serde::Deserialize;
 
 #[linkme::distributed_slice(TARGET_DISCOVERY)]
-fn discover(path: &Path) -> anyhow::Result<Vec<Box<dyn Target>>> {
+fn discover(path: &Path, config: &Config) -> anyhow::Result<Targets> {
+    let mut result: Targets = Vec::new();
+
+    if path.join("go.work").try_exists()? {
+        result.push(Box::new(GoWorkTarget::new(path, config.go.clone())?));
+    }
     if path.join("go.mod").try_exists()? {
-        Ok(vec![(Box::new(GoModTarget::new(&path)))])
-    } else {
-        Ok(Vec::new())
+        let workspace = find_workspace(path)?;
+        result.push(Box::new(GoModTarget::new(
+            path,
+            workspace,
+            config.go.clone(),
+        )));
+    }
+
+    Ok(result)
+}
+
+/// Options for Go targets, from the `[go]` section of `gentle.toml`.
+#[derive(Deserialize, Default, Clone, Debug)]
+pub struct GoConfig {
+    /// Run tests with the race detector.
+    #[serde(default)]
+    race: bool,
+
+    /// Passed to `go test -count`, `1` disables Go's own test caching.
+    count: Option<u32>,
+
+    /// Build tags used when testing, linting, building and listing sources.
+    #[serde(default)]
+    tags: Vec<String>,
+
+    /// File to write a coverage profile to, relative to each module.
+    coverprofile: Option<PathBuf>,
+}
+
+impl GoConfig {
+    fn tags_args(&self) -> Vec<String> {
+        if self.tags.is_empty() {
+            Vec::new()
+        } else {
+            vec![format!("-tags={}", self.tags.join(","))]
+        }
     }
+
+    fn test_args(&self, patterns: &[String]) -> Vec<String> {
+        let mut args = vec![String::from("test")];
+        if self.race {
+            args.push(String::from("-race"));
+        }
+        if let Some(count) = self.count {
+            args.push(format!("-count={count}"));
+        }
+        args.extend(self.tags_args());
+        if let Some(profile) = &self.coverprofile {
+            args.push(format!("-coverprofile={}", profile.display()));
+        }
+        args.extend(patterns.iter().cloned());
+        args
+    }
+
+    fn lint_args(&self, mode: Mode) -> Vec<String> {
+        let mut args = vec![String::from("run")];
+        if mode == Mode::Fix {
+            args.push(String::from("--fix"));
+        }
+        if !self.tags.is_empty() {
+            args.push(format!("--build-tags={}", self.tags.join(",")));
+        }
+        args
+    }
+
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        let mut inputs = vec![
+            tool_version("go", &["version"])?,
+            format!("go {}", self.test_args(&[]).join(" ")),
+        ];
+        inputs.extend(env_inputs(&["GOOS", "GOARCH", "GOFLAGS", "CGO_ENABLED"]));
+        Ok(inputs)
+    }
+}
+
+fn cache_dir() -> PathBuf {
+    std::env::var("GOCACHE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            Path::new(&std::env::var("HOME").unwrap_or(String::from("/"))).join(".cache/go-build")
+        })
+}
+
+fn go_test(dir: &Path, args: Vec<String>) -> anyhow::Result<()> {
+    Command::new("go")
+        .args(args)
+        .env("GOCACHE", cache_dir())
+        .current_dir(dir)
+        .output()?
+        .success_ok()
+        .map(|_| ())
+        // Failing tests are reported on stdout.
+        .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
 }
 
 pub struct GoModTarget {
     path: PathBuf,
+    /// Directory of the `go.work` file listing this module, if any.
+    workspace: Option<PathBuf>,
+    config: GoConfig,
 }
 
 impl GoModTarget {
-    pub fn new(path: &Path) -> Self {
-        Self { path: path.into() }
+    pub fn new(path: &Path, workspace: Option<PathBuf>, config: GoConfig) -> Self {
+        Self {
+            path: path.into(),
+            workspace,
+            config,
+        }
     }
 
-    fn cache_dir(&self) -> PathBuf {
-        std::env::var("GOCACHE")
-            .map(PathBuf::from)
-            .unwrap_or_else(|_| {
-                Path::new(&std::env::var("HOME").unwrap_or(String::from("/")))
-                    .join(".cache/go-build")
+    fn vet(&self) -> anyhow::Result<()> {
+        Command::new("go")
+            .arg("vet")
+            .args(self.config.tags_args())
+            .arg("./...")
+            .env("GOCACHE", cache_dir())
+            .current_dir(&self.path)
+            .output()
+            .context("Running go vet")?
+            .success_ok()
+            .map(|_| ())
+            .map_err(|out| anyhow::anyhow!(out.stderr))
+    }
+
+    /// The module's packages, along with the packages they and their tests depend on when
+    /// `deps` is set.
+    fn go_list(&self, deps: bool) -> anyhow::Result<Vec<GoPackage>> {
+        let mut command = Command::new("go");
+        command.args(["list", "-e", "-json"]);
+        if deps {
+            command.args(["-deps", "-test"]);
+        }
+        let output = command
+            .args(self.config.tags_args())
+            .arg("./...")
+            .env("GOCACHE", cache_dir())
+            .current_dir(&self.path)
+            .output()
+            .context("Running go list")?
+            .success_ok()
+            .map_err(|out| anyhow::anyhow!("{}", out.stderr))?;
+
+        parse_packages(&output.stdout)
+    }
+
+    fn go_mod(&self) -> anyhow::Result<GoMod> {
+        let output = Command::new("go")
+            .args(["mod", "edit", "-json"])
+            .current_dir(&self.path)
+            .output()
+            .context("Running go mod edit")?
+            .success_ok()
+            .map_err(|out| anyhow::anyhow!("{}", out.stderr))?;
+
+        Ok(serde_json::from_str(&output.stdout)?)
+    }
+
+    /// Directories of modules that `replace` directives point to on the local filesystem.
+    fn local_replacements(&self) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(self
+            .go_mod()?
+            .replace
+            .into_iter()
+            .map(|r| r.new.path)
+            .filter(|path| {
+                path.starts_with("./") || path.starts_with("../") || path.starts_with("/")
             })
-            .into()
+            .filter_map(|path| self.path.join(path).canonicalize().ok())
+            .filter_map(|dir| relative_to_current_dir(&dir).ok())
+            .collect())
+    }
+
+    /// Directories of the other modules in this module's workspace that it requires.
+    fn workspace_dependencies(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let Some(workspace) = &self.workspace else {
+            return Ok(Vec::new());
+        };
+
+        let mut dirs = HashMap::new();
+        let mut required = Vec::new();
+        for dir in workspace_modules(workspace)? {
+            let dir = simplify(&dir)?;
+            let go_mod = GoModTarget::new(&dir, None, self.config.clone()).go_mod()?;
+            if let Some(module) = go_mod.module {
+                dirs.insert(module.path, dir.clone());
+            }
+            required.push((dir, go_mod.require));
+        }
+
+        let graph = required
+            .into_iter()
+            .map(|(dir, require)| {
+                let deps = require.iter().filter_map(|r| dirs.get(&r.path).cloned());
+                (dir, deps.collect())
+            })
+            .collect();
+        Ok(acyclic_requirements(&graph, &simplify(&self.path)?))
     }
 }
 
 impl Display for GoModTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let package = self.path.display().to_string().replacen("./", "", 1);
-        write!(f, "//{package}:go_mod")
+        fmt_address(f, &self.path, self.kind())
     }
 }
 
 impl Target for GoModTarget {
+    fn kind(&self) -> &'static str {
+        "go_mod"
+    }
+
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
     fn perform_test(&self) -> anyhow::Result<()> {
-        Command::new("go")
-            .args(&["test"])
-            .env("GOCACHE", self.cache_dir())
-            .current_dir(&self.path)
-            .output()?
-            .success_ok()
-            .map(|_| ())
-            .map_err(|out| anyhow::anyhow!(out.stderr))
+        go_test(&self.path, self.config.test_args(&[String::from("./...")]))
     }
 
-    fn perform_lint(&self) -> anyhow::Result<()> {
+    fn perform_lint(&self, mode: Mode) -> anyhow::Result<()> {
         // TODO(shelbyd): Install required tools.
-        Command::new("golangci-lint")
-            .args(["--verbose"])
+        let output = match Command::new("golangci-lint")
+            .args(self.config.lint_args(mode))
             .current_dir(&self.path)
             .output()
-            .context("Running golangci-lint")?
+        {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return self.vet(),
+            output => output.context("Running golangci-lint")?,
+        };
+
+        output
             .success_ok()
             .map(|_| ())
-            .map_err(|out| anyhow::anyhow!(out.stderr))
+            .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
     }
 
-    fn perform_format(&self) -> anyhow::Result<()> {
-        let out = Command::new("go")
-            .args(["fmt"])
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()> {
+        let flag = match mode {
+            Mode::Check => "-d",
+            Mode::Fix => "-w",
+        };
+
+        // Only the module's own packages, skipping `vendor` and `testdata` like `./...` does.
+        let files = self
+            .go_list(false)?
+            .iter()
+            .flat_map(|p| p.go_files_to_format().map(|f| p.dir.join(f)))
+            .collect::<BTreeSet<_>>();
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let out = Command::new("gofmt")
+            .arg(flag)
+            .args(files)
             .current_dir(&self.path)
             .output()
-            .context("Running `go fmt`")?
+            .context("Running gofmt")?
             .success_ok()
             .map_err(|out| anyhow::anyhow!(out.stderr))?;
 
-        let modified = out
-            .stdout
-            .lines()
-            .map(|l| l.trim())
-            .filter(|l| !l.is_empty())
-            .collect::<Vec<&str>>();
-
-        if modified.is_empty() {
-            return Ok(());
+        if mode == Mode::Check && !out.stdout.trim().is_empty() {
+            anyhow::bail!("gofmt found unformatted files:\n{}", out.stdout);
         }
 
-        let padded = modified
-            .into_iter()
-            .map(|l| format!("    {l}"))
-            .collect::<Vec<_>>()
-            .join("\n");
-        Err(anyhow::anyhow!("go fmt modified files:\n{padded}"))
+        Ok(())
     }
 
     fn perform_build(&self, build: &Build) -> anyhow::Result<()> {
         let current_dir = std::env::current_dir()?;
 
         Command::new("go")
-            .args(&["build", "-o"])
-            .arg(&current_dir.join(&build.out))
-            .env("GOCACHE", self.cache_dir())
+            .arg("build")
+            .args(self.config.tags_args())
+            .arg("-o")
+            .arg(current_dir.join(&build.out))
+            .env("GOCACHE", cache_dir())
             .current_dir(&self.path)
             .output()?
             .success_ok()
@@ -104,11 +296,527 @@ impl Target for GoModTarget {
             .map_err(|out| anyhow::anyhow!(out.stderr))
     }
 
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        self.config.cache_key_inputs()
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [cache_dir()].into_iter().collect()
+    }
+
+    fn lock_files(&self) -> HashSet<PathBuf> {
+        [self.path.join("go.sum")]
+            .into_iter()
+            .filter(|p| p.exists())
+            .collect()
+    }
+
+    fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
+        let mut builder = FileSelector::builder()
+            .path(self.path.join("go.mod"))
+            .path(self.path.join("go.sum"));
+
+        if let Some(workspace) = &self.workspace {
+            builder = builder
+                .path(workspace.join("go.work"))
+                .path(workspace.join("go.work.sum"));
+        }
+
+        for dir in self.local_replacements()? {
+            builder = builder.path(dir.join("go.mod")).path(dir.join("go.sum"));
+        }
+
+        // Packages from the module cache are identified by go.sum, so only files inside the
+        // repository are tracked.
+        for path in package_files(&self.go_list(true)?) {
+            if let Ok(path) = relative_to_current_dir(&path) {
+                builder = builder.path(path);
+            }
+        }
+
+        Ok(Some(builder.build()))
+    }
+
+    fn dependencies(&self) -> anyhow::Result<HashSet<String>> {
+        Ok(self
+            .local_replacements()?
+            .into_iter()
+            .filter(|dir| dir.join("go.mod").exists())
+            .chain(self.workspace_dependencies()?)
+            .map(|dir| GoModTarget::new(&dir, None, self.config.clone()).to_string())
+            .collect())
+    }
+}
+
+/// Modules listed in a `go.work` file, tested together in workspace mode.
+pub struct GoWorkTarget {
+    path: PathBuf,
+    modules: Vec<GoModTarget>,
+    config: GoConfig,
+}
+
+impl GoWorkTarget {
+    pub fn new(path: &Path, config: GoConfig) -> anyhow::Result<Self> {
+        let modules = workspace_modules(path)?
+            .into_iter()
+            .map(|dir| GoModTarget::new(&dir, Some(path.to_path_buf()), config.clone()))
+            .collect();
+
+        Ok(Self {
+            path: path.into(),
+            modules,
+            config,
+        })
+    }
+}
+
+impl Display for GoWorkTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_address(f, &self.path, self.kind())
+    }
+}
+
+impl Target for GoWorkTarget {
+    fn kind(&self) -> &'static str {
+        "go_work"
+    }
+
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self) -> anyhow::Result<()> {
+        let root = simplify(&self.path)?;
+        let patterns = self
+            .modules
+            .iter()
+            .map(|m| {
+                let dir = simplify(&m.path)?;
+                let relative = dir.strip_prefix(&root).unwrap_or(&dir);
+                Ok(format!("./{}", relative.join("...").display()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        go_test(&self.path, self.config.test_args(&patterns))
+    }
+
+    fn perform_lint(&self, mode: Mode) -> anyhow::Result<()> {
+        self.modules.iter().try_for_each(|m| m.perform_lint(mode))
+    }
+
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()> {
+        self.modules.iter().try_for_each(|m| m.perform_format(mode))
+    }
+
+    fn perform_build(&self, build: &Build) -> anyhow::Result<()> {
+        self.modules.iter().try_for_each(|m| m.perform_build(build))
+    }
+
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        self.config.cache_key_inputs()
+    }
+
     fn cache_paths(&self) -> HashSet<PathBuf> {
-        [self.cache_dir()].into_iter().collect()
+        [cache_dir()].into_iter().collect()
     }
 
     fn lock_files(&self) -> HashSet<PathBuf> {
-        [self.path.join("go.sum")].into_iter().collect()
+        let mut result = self
+            .modules
+            .iter()
+            .flat_map(|m| m.lock_files())
+            .collect::<HashSet<_>>();
+        result.extend(Some(self.path.join("go.work.sum")).filter(|p| p.exists()));
+        result
+    }
+
+    fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
+        let mut result = FileSelector::builder()
+            .path(self.path.join("go.work"))
+            .path(self.path.join("go.work.sum"))
+            .build();
+
+        for module in &self.modules {
+            result.include(module.src_files()?.expect("implemented for GoModTarget"));
+        }
+
+        Ok(Some(result))
+    }
+
+    fn dependencies(&self) -> anyhow::Result<HashSet<String>> {
+        let members = self.members()?;
+
+        let mut result = HashSet::new();
+        for module in &self.modules {
+            result.extend(
+                module
+                    .dependencies()?
+                    .into_iter()
+                    .filter(|d| !members.contains(d)),
+            );
+        }
+        Ok(result)
+    }
+
+    fn members(&self) -> anyhow::Result<HashSet<String>> {
+        Ok(self.modules.iter().map(|m| m.to_string()).collect())
+    }
+}
+
+/// Directory of the `go.work` file that lists the module at `path`, if any.
+///
+/// Like the go command, only the nearest `go.work` is considered.
+fn find_workspace(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    for dir in path.ancestors() {
+        if !dir.join("go.work").try_exists()? {
+            continue;
+        }
+
+        let path = simplify(path)?;
+        let listed = workspace_modules(dir)?
+            .iter()
+            .any(|m| simplify(m).is_ok_and(|m| m == path));
+        return Ok(listed.then(|| dir.to_path_buf()));
+    }
+
+    Ok(None)
+}
+
+/// Directories of the modules in the `go.work` file in `dir`, skipping those outside of the
+/// current directory.
+fn workspace_modules(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let path = dir.join("go.work");
+    let contents = std::fs::read_to_string(&path).context(format!("Reading {path:?}"))?;
+
+    Ok(go_work_uses(&contents)
+        .into_iter()
+        .filter_map(|module| dir.join(module).canonicalize().ok())
+        .filter_map(|module| relative_to_current_dir(&module).ok())
+        .filter(|module| module.join("go.mod").exists())
+        .collect())
+}
+
+/// The modules `module` requires in a workspace, whose requirements are given by `graph`.
+///
+/// Workspace modules may require each other, directly or through other modules, which would
+/// make a dependency cycle. Such requirements are left out, and the modules run independently.
+fn acyclic_requirements(graph: &BTreeMap<PathBuf, Vec<PathBuf>>, module: &Path) -> Vec<PathBuf> {
+    let reaches_module = |from: &PathBuf| {
+        let mut visited = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(dir) = stack.pop() {
+            if dir == module {
+                return true;
+            }
+            if visited.insert(dir) {
+                stack.extend(graph.get(dir).into_iter().flatten());
+            }
+        }
+        false
+    };
+
+    graph
+        .get(module)
+        .into_iter()
+        .flatten()
+        .filter(|dep| *dep != module && !reaches_module(dep))
+        .cloned()
+        .collect()
+}
+
+/// Paths from the `use` directives of a `go.work` file.
+fn go_work_uses(contents: &str) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    let mut in_block = false;
+
+    for line in contents.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+
+        if in_block {
+            match line {
+                ")" => in_block = false,
+                "" => {}
+                path => result.push(PathBuf::from(unquote(path))),
+            }
+            continue;
+        }
+
+        let Some(rest) = line.strip_prefix("use") else {
+            continue;
+        };
+        if !rest.starts_with([' ', '\t', '(']) {
+            continue;
+        }
+
+        match rest.trim() {
+            "(" => in_block = true,
+            path => result.push(PathBuf::from(unquote(path))),
+        }
+    }
+
+    result
+}
+
+fn unquote(s: &str) -> &str {
+    s.trim_matches(|c| c == '"' || c == '`')
+}
+
+/// Parses the stream of JSON objects printed by `go list -json`.
+fn parse_packages(output: &str) -> anyhow::Result<Vec<GoPackage>> {
+    serde_json::Deserializer::from_str(output)
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .context("Parsing go list output")
+}
+
+/// Source files, embedded files and module files of non-standard `packages`.
+fn package_files(packages: &[GoPackage]) -> BTreeSet<PathBuf> {
+    let mut result = BTreeSet::new();
+
+    for package in packages.iter().filter(|p| !p.standard) {
+        if let Some(module) = &package.module {
+            let module = module.replace.as_deref().unwrap_or(module);
+            result.extend(module.go_mod.iter().cloned());
+            result.extend(module.dir.as_ref().map(|dir| dir.join("go.sum")));
+        }
+
+        let files = [
+            &package.go_files,
+            &package.cgo_files,
+            &package.c_files,
+            &package.h_files,
+            &package.s_files,
+            &package.test_go_files,
+            &package.x_test_go_files,
+            &package.embed_files,
+            &package.test_embed_files,
+            &package.x_test_embed_files,
+        ];
+        result.extend(files.into_iter().flatten().map(|f| package.dir.join(f)));
+    }
+
+    result
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct GoPackage {
+    dir: PathBuf,
+    #[serde(default)]
+    standard: bool,
+    module: Option<GoModule>,
+
+    #[serde(default)]
+    go_files: Vec<PathBuf>,
+    #[serde(default)]
+    cgo_files: Vec<PathBuf>,
+    #[serde(default, rename = "CFiles")]
+    c_files: Vec<PathBuf>,
+    #[serde(default, rename = "HFiles")]
+    h_files: Vec<PathBuf>,
+    #[serde(default, rename = "SFiles")]
+    s_files: Vec<PathBuf>,
+    #[serde(default)]
+    ignored_go_files: Vec<PathBuf>,
+    #[serde(default)]
+    test_go_files: Vec<PathBuf>,
+    #[serde(default, rename = "XTestGoFiles")]
+    x_test_go_files: Vec<PathBuf>,
+    #[serde(default)]
+    embed_files: Vec<PathBuf>,
+    #[serde(default)]
+    test_embed_files: Vec<PathBuf>,
+    #[serde(default, rename = "XTestEmbedFiles")]
+    x_test_embed_files: Vec<PathBuf>,
+}
+
+impl GoPackage {
+    /// Go files in the package's directory, including those excluded by build constraints.
+    fn go_files_to_format(&self) -> impl Iterator<Item = &PathBuf> {
+        [
+            &self.go_files,
+            &self.cgo_files,
+            &self.ignored_go_files,
+            &self.test_go_files,
+            &self.x_test_go_files,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct GoModule {
+    dir: Option<PathBuf>,
+    go_mod: Option<PathBuf>,
+    replace: Option<Box<GoModule>>,
+}
+
+/// Output of `go mod edit -json`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct GoMod {
+    module: Option<ModuleVersion>,
+    #[serde(default)]
+    require: Vec<ModuleVersion>,
+    #[serde(default)]
+    replace: Vec<Replace>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Replace {
+    new: ModuleVersion,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ModuleVersion {
+    path: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requirement_cycles_are_left_out() {
+        let graph = [
+            ("a", vec!["b"]),
+            ("b", vec!["a"]),
+            ("c", vec!["a", "d"]),
+            ("d", vec![]),
+        ]
+        .into_iter()
+        .map(|(dir, deps)| {
+            (
+                PathBuf::from(dir),
+                deps.into_iter().map(PathBuf::from).collect(),
+            )
+        })
+        .collect();
+
+        assert!(acyclic_requirements(&graph, Path::new("a")).is_empty());
+        assert!(acyclic_requirements(&graph, Path::new("b")).is_empty());
+        assert_eq!(
+            acyclic_requirements(&graph, Path::new("c")),
+            [PathBuf::from("a"), PathBuf::from("d")]
+        );
+    }
+
+    #[test]
+    fn test_args_from_config() {
+        let config = GoConfig {
+            race: true,
+            count: Some(1),
+            tags: vec![String::from("integration"), String::from("slow")],
+            coverprofile: Some(PathBuf::from("coverage.out")),
+        };
+
+        assert_eq!(
+            config.test_args(&[String::from("./...")]),
+            [
+                "test",
+                "-race",
+                "-count=1",
+                "-tags=integration,slow",
+                "-coverprofile=coverage.out",
+                "./..."
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_args_from_config() {
+        let config = GoConfig {
+            tags: vec![String::from("integration"), String::from("slow")],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.lint_args(Mode::Check),
+            ["run", "--build-tags=integration,slow"]
+        );
+        assert_eq!(GoConfig::default().lint_args(Mode::Fix), ["run", "--fix"]);
+    }
+
+    #[test]
+    fn parses_go_work_uses() {
+        let contents = r#"go 1.22
+
+use ./tools // Comment
+
+use (
+	./svc/api
+	"./svc/web"
+
+	../shared
+)
+"#;
+
+        assert_eq!(
+            go_work_uses(contents),
+            ["./tools", "./svc/api", "./svc/web", "../shared"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn collects_package_files() {
+        let output = r#"{
+	"Dir": "/usr/lib/go/src/fmt",
+	"ImportPath": "fmt",
+	"Standard": true,
+	"GoFiles": ["print.go"]
+}
+{
+	"Dir": "/repo/svc/web",
+	"ImportPath": "example.com/svc/web",
+	"Module": {
+		"Path": "example.com/svc",
+		"Main": true,
+		"Dir": "/repo/svc",
+		"GoMod": "/repo/svc/go.mod"
+	},
+	"GoFiles": ["web.go"],
+	"TestGoFiles": ["web_test.go"],
+	"XTestGoFiles": ["example_test.go"],
+	"EmbedPatterns": ["static/*"],
+	"EmbedFiles": ["static/index.html"]
+}
+{
+	"Dir": "/repo/shared",
+	"ImportPath": "example.com/shared",
+	"Module": {
+		"Path": "example.com/shared",
+		"Version": "v1.0.0",
+		"Replace": {
+			"Path": "../shared",
+			"Dir": "/repo/shared",
+			"GoMod": "/repo/shared/go.mod"
+		}
+	},
+	"GoFiles": ["shared.go"]
+}
+"#;
+
+        let files = package_files(&parse_packages(output).unwrap());
+
+        assert_eq!(
+            files,
+            [
+                "/repo/shared/go.mod",
+                "/repo/shared/go.sum",
+                "/repo/shared/shared.go",
+                "/repo/svc/go.mod",
+                "/repo/svc/go.sum",
+                "/repo/svc/web/example_test.go",
+                "/repo/svc/web/static/index.html",
+                "/repo/svc/web/web.go",
+                "/repo/svc/web/web_test.go",
+            ]
+            .map(PathBuf::from)
+            .into()
+        );
     }
 }
@@ -1,12 +1,26 @@
-use super::{file_selector::FileSelector, Build};
+use super::{
+    file_selector::{simplify, FileSelector},
+    Build, Config, Mode,
+};
 
+use anyhow::Context;
 use std::{collections::*, fmt::Display, path::*, process::*};
 
+mod buf;
 mod docker;
 mod go;
+mod node;
+mod python;
 mod rust;
+mod shell;
+mod terraform;
 
-pub fn targets() -> anyhow::Result<Vec<Box<dyn Target>>> {
+pub use buf::BufConfig;
+pub use docker::DockerConfig;
+pub use go::GoConfig;
+pub use terraform::TerraformConfig;
+
+pub fn targets(config: &Config) -> anyhow::Result<Targets> {
     let mut result = Vec::new();
 
     for entry in ignore::Walk::new("./") {
@@ -19,7 +33,7 @@ pub fn targets() -> anyhow::Result<Vec<Box<dyn Target>>> {
         let path = entry.into_path();
 
         for factory in TARGET_DISCOVERY {
-            result.extend(factory(&path)?);
+            result.extend(factory(&path, config)?);
         }
     }
 
@@ -27,15 +41,28 @@ pub fn targets() -> anyhow::Result<Vec<Box<dyn Target>>> {
 }
 
 #[linkme::distributed_slice]
-static TARGET_DISCOVERY: [fn(&Path) -> anyhow::Result<Vec<Box<dyn Target>>>] = [..];
+static TARGET_DISCOVERY: [fn(&Path, &Config) -> anyhow::Result<Targets>] = [..];
+
+pub type Targets = Vec<Box<dyn Target>>;
 
 pub trait Target: Display + Send + Sync + 'static {
+    /// Kind of target, like `rust_crate`.
+    fn kind(&self) -> &'static str;
+    /// Directory the target was discovered in.
+    fn dir(&self) -> &Path;
+
     fn perform_test(&self) -> anyhow::Result<()>;
     // TODO(shelbyd): Default to successful and logging implementation.
-    fn perform_lint(&self) -> anyhow::Result<()>;
-    fn perform_format(&self) -> anyhow::Result<()>;
+    fn perform_lint(&self, mode: Mode) -> anyhow::Result<()>;
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()>;
     fn perform_build(&self, build: &Build) -> anyhow::Result<()>;
 
+    /// Whether [`Target::perform_build`] writes everything it produces to the build output, so a
+    /// cached build can be restored in place of building again.
+    fn caches_build(&self) -> bool {
+        true
+    }
+
     fn cache_paths(&self) -> HashSet<PathBuf> {
         Default::default()
     }
@@ -47,6 +74,87 @@ pub trait Target: Display + Send + Sync + 'static {
     fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
         Ok(None)
     }
+
+    /// Tool versions and environment that can affect the result of actions.
+    ///
+    /// Included in cache keys alongside the hash of [`Target::src_files`].
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Inputs that only affect linting, included in its cache key on top of
+    /// [`Target::cache_key_inputs`].
+    fn lint_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Addresses of targets that must succeed before this target runs.
+    fn dependencies(&self) -> anyhow::Result<HashSet<String>> {
+        Ok(Default::default())
+    }
+
+    /// Addresses of targets whose actions this target also performs, like the members of a
+    /// workspace.
+    fn members(&self) -> anyhow::Result<HashSet<String>> {
+        Ok(Default::default())
+    }
+}
+
+/// Formats a target address, like `//some/dir:rust_crate`.
+fn fmt_address(f: &mut std::fmt::Formatter<'_>, dir: &Path, name: &str) -> std::fmt::Result {
+    match simplify(dir) {
+        Ok(dir) => write!(f, "//{}:{name}", dir.display()),
+        Err(_) => write!(f, "//{}:{name}", dir.display()),
+    }
+}
+
+/// Diff between the `original` and `formatted` contents of `path`, in unified diff format.
+fn unified_diff(path: &Path, original: &str, formatted: &str) -> String {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    let path = path.strip_prefix(current_dir).unwrap_or(path);
+    let path = simplify(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string();
+    similar::TextDiff::from_lines(original, formatted)
+        .unified_diff()
+        .header(&format!("a/{path}"), &format!("b/{path}"))
+        .to_string()
+}
+
+/// `path` relative to the current directory, failing for paths outside of it.
+fn relative_to_current_dir(path: &Path) -> anyhow::Result<PathBuf> {
+    if path.is_relative() {
+        return Ok(path.to_path_buf());
+    }
+
+    let current_dir = std::env::current_dir()?.canonicalize()?;
+    let relative = path
+        .strip_prefix(&current_dir)
+        .map_err(|_| anyhow::anyhow!("{path:?} is outside of {current_dir:?}"))?;
+    Ok(Path::new(".").join(relative))
+}
+
+/// Whether `program` is an executable in one of the directories on `PATH`.
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+fn tool_version(program: &str, args: &[&str]) -> anyhow::Result<String> {
+    Command::new(program)
+        .args(args)
+        .output()
+        .context(format!("Running {program}"))?
+        .success_ok()
+        .map(|out| out.stdout)
+        .map_err(|out| anyhow::anyhow!("{}", out.stderr))
+}
+
+fn env_inputs(names: &'static [&'static str]) -> impl Iterator<Item = String> {
+    names
+        .iter()
+        .map(|name| format!("{name}={}", std::env::var(name).unwrap_or_default()))
 }
 
 trait OutputExt {
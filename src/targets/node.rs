@@ -0,0 +1,513 @@
+use super::*;
+
+use anyhow::Context;
+use serde::Deserialize;
+use std::sync::Mutex;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+fn discover(path: &Path, _: &Config) -> anyhow::Result<Targets> {
+    let in_node_modules = path.components().any(|c| c.as_os_str() == "node_modules");
+    if in_node_modules || !path.join("package.json").try_exists()? {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Box::new(NodePackageTarget::new(path)?)])
+}
+
+pub struct NodePackageTarget {
+    path: PathBuf,
+    manifest: PackageJson,
+    /// Directory of the lock file, which is the workspace root for packages in a workspace.
+    root: PathBuf,
+    manager: PackageManager,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+}
+
+/// Held while installing dependencies, so packages in one workspace don't install concurrently.
+static INSTALL: Mutex<()> = Mutex::new(());
+
+impl NodePackageTarget {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let manifest = PackageJson::read(path)?;
+
+        let (root, manager) = path
+            .ancestors()
+            .find_map(|dir| Some((dir.to_path_buf(), PackageManager::from_lock_files(dir)?)))
+            .unwrap_or((path.to_path_buf(), PackageManager::Npm));
+
+        Ok(NodePackageTarget {
+            path: path.to_path_buf(),
+            manifest,
+            root,
+            manager,
+        })
+    }
+
+    /// Installs dependencies if they haven't been yet.
+    fn install(&self) -> anyhow::Result<()> {
+        let _lock = INSTALL.lock().unwrap_or_else(|e| e.into_inner());
+        if self.root.join("node_modules").try_exists()? {
+            return Ok(());
+        }
+
+        // Without a lock file there's nothing to install from exactly, so one is created.
+        let args = if self.manager.lock_file(&self.root).try_exists()? {
+            self.manager.install_args()
+        } else {
+            &["install"]
+        };
+        Command::new(self.manager.program())
+            .args(args)
+            .current_dir(&self.root)
+            .output()
+            .context(format!("Running {} install", self.manager.program()))?
+            .success_ok()
+            .map(|_| ())
+            .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
+    }
+
+    /// Runs the first of `scripts` the package defines, if any.
+    fn run_script(&self, scripts: &[&str]) -> anyhow::Result<()> {
+        let Some(script) = scripts
+            .iter()
+            .find(|s| self.manifest.scripts.contains_key(**s))
+        else {
+            return Ok(());
+        };
+
+        self.install()?;
+        Command::new(self.manager.program())
+            .args(["run", script])
+            .current_dir(&self.path)
+            .output()
+            .context(format!("Running {} run {script}", self.manager.program()))?
+            .success_ok()
+            .map(|_| ())
+            .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
+    }
+
+    /// Other packages in the same workspace.
+    fn workspace_packages(&self) -> anyhow::Result<Vec<NodePackageTarget>> {
+        let root_manifest = PackageJson::read(&self.root).ok();
+        let mut patterns = root_manifest
+            .and_then(|m| m.workspaces)
+            .map(Workspaces::into_patterns)
+            .unwrap_or_default();
+
+        let pnpm_workspace = self.root.join("pnpm-workspace.yaml");
+        if pnpm_workspace.try_exists()? {
+            let contents = std::fs::read_to_string(&pnpm_workspace)
+                .context(format!("Reading {pnpm_workspace:?}"))?;
+            patterns.extend(pnpm_workspace_packages(&contents));
+        }
+
+        let mut dirs = BTreeSet::new();
+        for pattern in &patterns {
+            let (negated, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern.as_str()),
+            };
+            let root = glob::Pattern::escape(&self.root.display().to_string());
+            let pattern = Path::new(&root).join(pattern).join("package.json");
+
+            for manifest in glob::glob(&pattern.display().to_string())? {
+                // Packages outside of the current directory aren't targets.
+                let Ok(dir) = simplify(manifest?.parent().expect("joined onto dir")) else {
+                    continue;
+                };
+                if negated {
+                    dirs.remove(&dir);
+                } else {
+                    dirs.insert(dir);
+                }
+            }
+        }
+
+        let path = simplify(&self.path)?;
+        dirs.into_iter()
+            .filter(|dir| *dir != path)
+            .map(|dir| NodePackageTarget::new(&dir))
+            .collect()
+    }
+
+    /// Packages from the same workspace, or local `file:` and `link:` paths, that this package
+    /// depends on, including `devDependencies` if `dev` is set.
+    fn local_dependencies(&self, dev: bool) -> anyhow::Result<Vec<NodePackageTarget>> {
+        let dependencies = self.manifest.dependencies(dev);
+
+        let mut result = Vec::new();
+        for package in self.workspace_packages()? {
+            if let Some(name) = &package.manifest.name {
+                if dependencies.contains_key(name.as_str()) {
+                    result.push(package);
+                }
+            }
+        }
+
+        for spec in dependencies.values() {
+            let Some(path) = spec
+                .strip_prefix("file:")
+                .or_else(|| spec.strip_prefix("link:"))
+            else {
+                continue;
+            };
+
+            let dir = self.path.join(path);
+            let Ok(dir) = relative_to_current_dir(&dir.canonicalize().unwrap_or(dir)) else {
+                continue;
+            };
+            if dir.join("package.json").try_exists()?
+                && !result
+                    .iter()
+                    .any(|p| simplify(&p.path).ok() == simplify(&dir).ok())
+            {
+                result.push(NodePackageTarget::new(&dir)?);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn collect_src_files(
+        &self,
+        result: &mut FileSelector,
+        visited: &mut HashSet<PathBuf>,
+    ) -> anyhow::Result<()> {
+        if !visited.insert(simplify(&self.path)?) {
+            return Ok(());
+        }
+
+        let mut builder = FileSelector::builder()
+            .path(self.manager.lock_file(&self.root))
+            .path(self.path.join("package.json"))
+            .path(self.root.join("package.json"))
+            .path(self.root.join("pnpm-workspace.yaml"))
+            .path(self.root.join(".npmrc"));
+
+        // Nested packages are targets of their own.
+        let walk = ignore::WalkBuilder::new(&self.path)
+            .hidden(false)
+            .filter_entry(|entry| {
+                let is_nested_package = entry.depth() > 0
+                    && entry.file_type().is_some_and(|t| t.is_dir())
+                    && entry.path().join("package.json").exists();
+                entry.file_name() != "node_modules"
+                    && entry.file_name() != ".git"
+                    && !is_nested_package
+            })
+            .build();
+        for entry in walk {
+            let entry = entry?;
+            if entry.file_type().is_some_and(|t| t.is_file()) {
+                builder = builder.path(entry.path());
+            }
+        }
+        result.include(builder.build());
+
+        // Scripts of a workspace's root package usually run those of the whole workspace.
+        let mut packages = self.local_dependencies(true)?;
+        if simplify(&self.path)? == simplify(&self.root)? {
+            packages.extend(self.workspace_packages()?);
+        }
+        for package in packages {
+            package.collect_src_files(result, visited)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PackageManager {
+    fn from_lock_files(dir: &Path) -> Option<PackageManager> {
+        [
+            PackageManager::Pnpm,
+            PackageManager::Yarn,
+            PackageManager::Npm,
+        ]
+        .into_iter()
+        .find(|m| m.lock_file(dir).exists())
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::Pnpm => "pnpm",
+            PackageManager::Yarn => "yarn",
+        }
+    }
+
+    fn lock_file(self, dir: &Path) -> PathBuf {
+        match self {
+            PackageManager::Npm => dir.join("package-lock.json"),
+            PackageManager::Pnpm => dir.join("pnpm-lock.yaml"),
+            PackageManager::Yarn => dir.join("yarn.lock"),
+        }
+    }
+
+    fn install_args(self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Npm => &["ci"],
+            PackageManager::Pnpm => &["install", "--frozen-lockfile"],
+            PackageManager::Yarn => &["install", "--frozen-lockfile"],
+        }
+    }
+
+    /// Where the package manager caches downloaded packages.
+    fn store_dir(self) -> PathBuf {
+        let home = PathBuf::from(std::env::var("HOME").unwrap_or(String::from("/")));
+        let var = |name: &str| std::env::var_os(name).map(PathBuf::from);
+
+        match self {
+            PackageManager::Npm => var("npm_config_cache").unwrap_or_else(|| home.join(".npm")),
+            PackageManager::Pnpm => var("XDG_DATA_HOME")
+                .unwrap_or_else(|| home.join(".local/share"))
+                .join("pnpm/store"),
+            PackageManager::Yarn => {
+                var("YARN_CACHE_FOLDER").unwrap_or_else(|| home.join(".cache/yarn"))
+            }
+        }
+    }
+}
+
+impl Display for NodePackageTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_address(f, &self.path, self.kind())
+    }
+}
+
+impl Target for NodePackageTarget {
+    fn kind(&self) -> &'static str {
+        "node_package"
+    }
+
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self) -> anyhow::Result<()> {
+        self.run_script(&["test"])
+    }
+
+    fn perform_lint(&self, mode: Mode) -> anyhow::Result<()> {
+        match mode {
+            Mode::Check => self.run_script(&["lint"]),
+            Mode::Fix => self.run_script(&["lint:fix", "lint"]),
+        }
+    }
+
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()> {
+        // A `format` script usually rewrites files, so it's only run to fix them.
+        match mode {
+            Mode::Check => self.run_script(&["format:check"]),
+            Mode::Fix => self.run_script(&["format"]),
+        }
+    }
+
+    fn perform_build(&self, _: &Build) -> anyhow::Result<()> {
+        self.run_script(&["build"])
+    }
+
+    fn caches_build(&self) -> bool {
+        // Build scripts write their output into the package, wherever they like.
+        false
+    }
+
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        let mut inputs = vec![
+            tool_version("node", &["--version"])?,
+            tool_version(self.manager.program(), &["--version"])?,
+        ];
+        inputs.extend(env_inputs(&["NODE_ENV", "NODE_OPTIONS"]));
+        Ok(inputs)
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [
+            self.path.join("node_modules"),
+            self.root.join("node_modules"),
+            self.manager.store_dir(),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn lock_files(&self) -> HashSet<PathBuf> {
+        [self.manager.lock_file(&self.root)]
+            .into_iter()
+            .filter(|f| f.exists())
+            .collect()
+    }
+
+    fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
+        let mut result = FileSelector::builder().build();
+        self.collect_src_files(&mut result, &mut HashSet::new())?;
+        Ok(Some(result))
+    }
+
+    fn dependencies(&self) -> anyhow::Result<HashSet<String>> {
+        Ok(self
+            .local_dependencies(false)?
+            .into_iter()
+            .map(|p| p.to_string())
+            .collect())
+    }
+}
+
+/// Package patterns from a `pnpm-workspace.yaml` file.
+fn pnpm_workspace_packages(contents: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut in_packages = false;
+
+    for line in contents.lines() {
+        let line = line.split(" #").next().unwrap_or_default().trim_end();
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if !line.starts_with([' ', '\t', '-']) {
+            in_packages = line.trim() == "packages:";
+            continue;
+        }
+
+        if let Some(item) = line.trim().strip_prefix('-').filter(|_| in_packages) {
+            result.push(item.trim().trim_matches(['\'', '"']).to_string());
+        }
+    }
+
+    result
+}
+
+#[derive(Deserialize, Debug)]
+struct PackageJson {
+    name: Option<String>,
+    #[serde(default)]
+    scripts: BTreeMap<String, String>,
+    workspaces: Option<Workspaces>,
+
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default, rename = "devDependencies")]
+    dev_dependencies: BTreeMap<String, String>,
+    #[serde(default, rename = "peerDependencies")]
+    peer_dependencies: BTreeMap<String, String>,
+    #[serde(default, rename = "optionalDependencies")]
+    optional_dependencies: BTreeMap<String, String>,
+}
+
+impl PackageJson {
+    fn read(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join("package.json");
+        let contents = std::fs::read_to_string(&path).context(format!("Reading {path:?}"))?;
+        serde_json::from_str(&contents).context(format!("Parsing {path:?}"))
+    }
+
+    /// Every dependency by name, with its version spec, leaving out `devDependencies` unless
+    /// `dev` is set.
+    fn dependencies(&self, dev: bool) -> BTreeMap<&str, &str> {
+        let dev_dependencies = dev.then_some(&self.dev_dependencies).into_iter().flatten();
+        self.dependencies
+            .iter()
+            .chain(&self.peer_dependencies)
+            .chain(&self.optional_dependencies)
+            .chain(dev_dependencies)
+            .map(|(name, spec)| (name.as_str(), spec.as_str()))
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Workspaces {
+    Patterns(Vec<String>),
+    Object {
+        #[serde(default)]
+        packages: Vec<String>,
+    },
+}
+
+impl Workspaces {
+    fn into_patterns(self) -> Vec<String> {
+        match self {
+            Workspaces::Patterns(patterns) | Workspaces::Object { packages: patterns } => patterns,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pnpm_workspace() {
+        let contents = "\
+# Packages in the workspace.
+packages:
+  - 'packages/*'
+  - \"apps/**\" # Apps too.
+  - '!**/test/**'
+catalog:
+  - not-a-package
+";
+
+        assert_eq!(
+            pnpm_workspace_packages(contents),
+            ["packages/*", "apps/**", "!**/test/**"]
+        );
+    }
+
+    #[test]
+    fn workspaces_field_forms() {
+        let list: PackageJson = serde_json::from_str(r#"{"workspaces": ["packages/*"]}"#).unwrap();
+        let object: PackageJson =
+            serde_json::from_str(r#"{"workspaces": {"packages": ["apps/*"]}}"#).unwrap();
+
+        assert_eq!(list.workspaces.unwrap().into_patterns(), ["packages/*"]);
+        assert_eq!(object.workspaces.unwrap().into_patterns(), ["apps/*"]);
+    }
+
+    #[test]
+    fn src_files_include_unpublished_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for (file, contents) in [
+            ("package.json", r#"{"name": "web", "files": ["dist"]}"#),
+            ("src/index.js", ""),
+            ("tsconfig.json", "{}"),
+            ("node_modules/dep/index.js", ""),
+        ] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        let target = NodePackageTarget::new(dir.path()).unwrap();
+        let src_files = target.src_files().unwrap().unwrap();
+        let includes = |file: &str| src_files.includes(dir.path().join(file));
+
+        assert!(includes("src/index.js"));
+        assert!(includes("tsconfig.json"));
+        assert!(!includes("node_modules/dep/index.js"));
+    }
+
+    #[test]
+    fn dev_dependencies_are_optional() {
+        let manifest: PackageJson = serde_json::from_str(
+            r#"{"dependencies": {"a": "1"}, "peerDependencies": {"b": "2"}, "devDependencies": {"c": "3"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.dependencies(false).into_keys().collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert_eq!(
+            manifest.dependencies(true).into_keys().collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+    }
+}
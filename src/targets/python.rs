@@ -0,0 +1,337 @@
+use super::*;
+
+use anyhow::Context;
+
+/// Files whose presence makes a directory a Python project.
+const MARKERS: [&str; 3] = ["pyproject.toml", "setup.py", "requirements.txt"];
+
+/// Files that list a project's dependencies, reinstalled into the virtualenv when they change.
+const REQUIREMENTS: [&str; 5] = [
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+    "requirements.txt",
+    "requirements-dev.txt",
+];
+
+/// Script exiting successfully if the module named by its argument can be imported.
+const HAS_MODULE: &str =
+    "import importlib.util, sys; sys.exit(importlib.util.find_spec(sys.argv[1]) is None)";
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+fn discover(path: &Path, _: &Config) -> anyhow::Result<Targets> {
+    for marker in MARKERS {
+        if path.join(marker).try_exists()? {
+            return Ok(vec![Box::new(PythonProjectTarget::new(path))]);
+        }
+    }
+
+    Ok(Vec::new())
+}
+
+pub struct PythonProjectTarget {
+    path: PathBuf,
+}
+
+impl PythonProjectTarget {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn venv(&self) -> PathBuf {
+        self.path.join(".venv")
+    }
+
+    /// Whether the project can be installed as a package, rather than only listing requirements.
+    fn is_package(&self) -> bool {
+        self.path.join("pyproject.toml").exists() || self.path.join("setup.py").exists()
+    }
+
+    /// Creates the virtualenv and installs the project's dependencies into it, unless they're
+    /// already installed.
+    fn ensure_venv(&self) -> anyhow::Result<()> {
+        let venv = self.venv();
+        let stamp_path = venv.join(".gentle-requirements");
+
+        let stamp = self.requirements_stamp()?;
+        if std::fs::read_to_string(&stamp_path).ok().as_deref() == Some(stamp.as_str()) {
+            return Ok(());
+        }
+
+        if !venv.join("bin/python").try_exists()? {
+            run(Command::new("python3").args(["-m", "venv"]).arg(&venv))?;
+        }
+
+        let python = self.python()?;
+        let pip = || {
+            let mut command = Command::new(&python);
+            command
+                .args(["-m", "pip", "install", "--quiet"])
+                .current_dir(&self.path);
+            command
+        };
+        for requirements in ["requirements.txt", "requirements-dev.txt"] {
+            if self.path.join(requirements).try_exists()? {
+                run(pip().args(["-r", requirements]))?;
+            }
+        }
+        if self.is_package() {
+            run(pip().args(["--editable", "."]))?;
+        }
+
+        std::fs::write(&stamp_path, stamp).context(format!("Writing {stamp_path:?}"))
+    }
+
+    /// Hash of the files listing the project's dependencies, stored in the virtualenv once
+    /// they're installed.
+    fn requirements_stamp(&self) -> anyhow::Result<String> {
+        let requirements = REQUIREMENTS
+            .iter()
+            .map(|f| self.path.join(f))
+            .filter(|f| f.exists());
+        Ok(crate::hash_files::hash_files(requirements)?
+            .to_hex()
+            .to_string())
+    }
+
+    /// Command running the `tool` module with the virtualenv's interpreter, or `None` if it isn't
+    /// installed there, so tools from outside the project are never used.
+    fn tool(&self, tool: &str) -> anyhow::Result<Option<Command>> {
+        let python = self.python()?;
+        let installed = Command::new(&python)
+            .args(["-c", HAS_MODULE, tool])
+            .output()
+            .context(format!("Running {python:?}"))?
+            .status
+            .success();
+        if !installed {
+            return Ok(None);
+        }
+
+        let mut command = Command::new(python);
+        command.args(["-m", tool]).current_dir(&self.path);
+        Ok(Some(command))
+    }
+
+    fn python(&self) -> anyhow::Result<PathBuf> {
+        Ok(std::env::current_dir()?
+            .join(self.venv())
+            .join("bin/python"))
+    }
+}
+
+fn run(command: &mut Command) -> anyhow::Result<()> {
+    let program = command.get_program().to_string_lossy().to_string();
+    command
+        .output()
+        .context(format!("Running {program}"))?
+        .success_ok()
+        .map(|_| ())
+        .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
+}
+
+impl Display for PythonProjectTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_address(f, &self.path, self.kind())
+    }
+}
+
+impl Target for PythonProjectTarget {
+    fn kind(&self) -> &'static str {
+        "python_project"
+    }
+
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self) -> anyhow::Result<()> {
+        self.ensure_venv()?;
+
+        let mut pytest = self
+            .tool("pytest")?
+            .ok_or_else(|| anyhow::anyhow!("pytest isn't installed in {:?}", self.venv()))?;
+        let output = pytest.output().context("Running pytest")?;
+
+        // Exit code 5 means no tests were collected.
+        if output.status.code() == Some(5) {
+            return Ok(());
+        }
+        output
+            .success_ok()
+            .map(|_| ())
+            .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
+    }
+
+    fn perform_lint(&self, mode: Mode) -> anyhow::Result<()> {
+        self.ensure_venv()?;
+
+        if let Some(mut ruff) = self.tool("ruff")? {
+            ruff.arg("check");
+            if mode == Mode::Fix {
+                ruff.arg("--fix");
+            }
+            return run(ruff.arg("."));
+        }
+
+        // flake8 has no fixes to apply.
+        let mut flake8 = self
+            .tool("flake8")?
+            .ok_or_else(|| anyhow::anyhow!("Found neither ruff nor flake8 in {:?}", self.venv()))?;
+        run(flake8.args(["--extend-exclude=.venv", "."]))
+    }
+
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()> {
+        self.ensure_venv()?;
+
+        let mut command = match self.tool("black")? {
+            Some(black) => black,
+            None => {
+                let mut ruff = self.tool("ruff")?.ok_or_else(|| {
+                    anyhow::anyhow!("Found neither black nor ruff in {:?}", self.venv())
+                })?;
+                ruff.arg("format");
+                ruff
+            }
+        };
+
+        // Both formatters take the same flags.
+        if mode == Mode::Check {
+            command.args(["--check", "--diff"]);
+        }
+        run(command.arg("."))
+    }
+
+    fn perform_build(&self, build: &Build) -> anyhow::Result<()> {
+        if !self.is_package() {
+            return Ok(());
+        }
+        self.ensure_venv()?;
+
+        let out = std::env::current_dir()?.join(&build.out);
+        run(Command::new(self.python()?)
+            .args(["-m", "pip", "wheel", "--quiet", "--no-deps", "--wheel-dir"])
+            .arg(out)
+            .arg(".")
+            .current_dir(&self.path))
+    }
+
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        let mut inputs = vec![tool_version("python3", &["--version"])?];
+        inputs.extend(env_inputs(&["PYTHONPATH"]));
+        Ok(inputs)
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        let pip_cache = std::env::var_os("PIP_CACHE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                Path::new(&std::env::var("HOME").unwrap_or(String::from("/"))).join(".cache/pip")
+            });
+
+        [self.venv(), pip_cache].into_iter().collect()
+    }
+
+    fn lock_files(&self) -> HashSet<PathBuf> {
+        [
+            "requirements.txt",
+            "requirements-dev.txt",
+            "poetry.lock",
+            "uv.lock",
+            "Pipfile.lock",
+        ]
+        .into_iter()
+        .map(|f| self.path.join(f))
+        .filter(|f| f.exists())
+        .collect()
+    }
+
+    fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
+        let mut builder = FileSelector::builder();
+
+        // Nested projects are targets of their own.
+        let walk = ignore::WalkBuilder::new(&self.path)
+            .hidden(false)
+            .filter_entry(|entry| {
+                let is_nested_project = entry.depth() > 0
+                    && entry.file_type().is_some_and(|t| t.is_dir())
+                    && MARKERS.iter().any(|m| entry.path().join(m).exists());
+                let name = entry.file_name();
+                name != ".venv" && name != "__pycache__" && name != ".git" && !is_nested_project
+            })
+            .build();
+        for entry in walk {
+            let entry = entry?;
+            if entry.file_type().is_some_and(|t| t.is_file()) {
+                builder = builder.path(entry.path());
+            }
+        }
+
+        Ok(Some(builder.build()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(files: &[&str]) -> (tempfile::TempDir, PythonProjectTarget) {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let target = PythonProjectTarget::new(dir.path());
+        (dir, target)
+    }
+
+    #[test]
+    fn detects_packages_and_lock_files() {
+        let (dir, target) = project(&["requirements.txt", "uv.lock"]);
+        assert!(!target.is_package());
+        assert_eq!(
+            target.lock_files(),
+            ["requirements.txt", "uv.lock"]
+                .map(|f| dir.path().join(f))
+                .into_iter()
+                .collect()
+        );
+
+        let (_dir, target) = project(&["pyproject.toml"]);
+        assert!(target.is_package());
+        assert!(target.lock_files().is_empty());
+    }
+
+    #[test]
+    fn requirement_changes_reinstall_venv() {
+        let (dir, target) = project(&["requirements.txt", "main.py"]);
+        let stamp = target.requirements_stamp().unwrap();
+
+        std::fs::write(dir.path().join("main.py"), "print()").unwrap();
+        assert_eq!(target.requirements_stamp().unwrap(), stamp);
+
+        std::fs::write(dir.path().join("requirements.txt"), "requests").unwrap();
+        assert_ne!(target.requirements_stamp().unwrap(), stamp);
+    }
+
+    #[test]
+    fn src_files_leave_out_venv_and_nested_projects() {
+        let (dir, target) = project(&[
+            "pyproject.toml",
+            "pkg/__init__.py",
+            "pkg/__pycache__/__init__.cpython-312.pyc",
+            ".venv/lib/site.py",
+            "tools/requirements.txt",
+            "tools/tool.py",
+        ]);
+        let src_files = target.src_files().unwrap().unwrap();
+        let includes = |file: &str| src_files.includes(dir.path().join(file));
+
+        assert!(includes("pyproject.toml"));
+        assert!(includes("pkg/__init__.py"));
+        assert!(!includes("pkg/__pycache__/__init__.cpython-312.pyc"));
+        assert!(!includes(".venv/lib/site.py"));
+        assert!(!includes("tools/tool.py"));
+    }
+}
@@ -2,96 +2,469 @@ use super::*;
 
 use anyhow::Context;
 use serde::Deserialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
 
 #[linkme::distributed_slice(TARGET_DISCOVERY)]
-fn discover(path: &Path) -> anyhow::Result<Vec<Box<dyn Target>>> {
-    if path.join("Cargo.toml").try_exists()? {
-        Ok(vec![(Box::new(RustCargoTarget::new(&path)))])
-    } else {
-        Ok(Vec::new())
+fn discover(path: &Path, _: &Config) -> anyhow::Result<Targets> {
+    if !path.join("Cargo.toml").try_exists()? {
+        return Ok(Vec::new());
     }
+
+    let cargo_toml = CargoTomlFile::read(path)?;
+    let mut result: Targets = Vec::new();
+
+    if let Some(workspace) = &cargo_toml.workspace {
+        result.push(Box::new(RustCargoTarget {
+            path: path.into(),
+            kind: Kind::Workspace {
+                members: workspace.member_dirs(path)?,
+            },
+        }));
+    }
+    if cargo_toml.package.is_some() {
+        result.push(Box::new(RustCargoTarget::package(path)?));
+    }
+
+    Ok(result)
 }
 
 pub struct RustCargoTarget {
     path: PathBuf,
+    kind: Kind,
+}
+
+enum Kind {
+    /// A package outside of any workspace.
+    Crate,
+    /// A workspace, whose actions cover every member.
+    Workspace { members: Vec<PathBuf> },
+    /// A package in a workspace, whose actions are performed with `-p`.
+    Member { workspace: PathBuf, name: String },
 }
 
 impl RustCargoTarget {
-    fn new(path: &Path) -> Self {
-        Self { path: path.into() }
+    /// The target for the package in `path`, as a member of its workspace if it has one.
+    fn package(path: &Path) -> anyhow::Result<Self> {
+        // Like the directories targets are discovered in, like `./` or `./some/dir`.
+        let path = Path::new(".").join(simplify(path)?);
+        let cargo_toml = CargoTomlFile::read(&path)?;
+        let package = cargo_toml
+            .package
+            .ok_or_else(|| anyhow::anyhow!("{path:?} is not a package"))?;
+
+        let kind = match find_workspace(&path, &package)? {
+            Some(workspace) => Kind::Member {
+                workspace,
+                name: package.name,
+            },
+            None => Kind::Crate,
+        };
+
+        Ok(RustCargoTarget { path, kind })
+    }
+
+    /// Directory containing the manifest that cargo commands run against.
+    fn root(&self) -> &Path {
+        match &self.kind {
+            Kind::Crate | Kind::Workspace { .. } => &self.path,
+            Kind::Member { workspace, .. } => workspace,
+        }
+    }
+
+    fn members(&self) -> anyhow::Result<Vec<RustCargoTarget>> {
+        match &self.kind {
+            Kind::Workspace { members } => members
+                .iter()
+                .map(|m| RustCargoTarget::package(m))
+                .collect(),
+            Kind::Crate | Kind::Member { .. } => Ok(Vec::new()),
+        }
+    }
+
+    /// A cargo command running `subcommand` on the packages of this target.
+    fn cargo(&self, subcommand: &str) -> Command {
+        let mut command = Command::new("cargo");
+        command.args([
+            subcommand,
+            "--manifest-path",
+            &self.root().join("Cargo.toml").to_string_lossy(),
+        ]);
+
+        match &self.kind {
+            Kind::Crate => {}
+            Kind::Workspace { .. } if subcommand == "fmt" => {
+                command.arg("--all");
+            }
+            Kind::Workspace { .. } => {
+                command.arg("--workspace");
+            }
+            Kind::Member { name, .. } => {
+                command.args(["-p", name]);
+            }
+        }
+
+        command
+    }
+
+    fn edition(&self) -> anyhow::Result<String> {
+        let cargo_toml = CargoTomlFile::read(&self.path)?;
+
+        let edition = match cargo_toml.package.and_then(|p| p.edition) {
+            Some(toml::Value::String(edition)) => Some(edition),
+            // Inherited with `edition.workspace = true`, or a virtual workspace manifest.
+            _ => CargoTomlFile::read(self.root())?
+                .workspace
+                .and_then(|w| w.package)
+                .and_then(|p| p.edition),
+        };
+
+        Ok(edition.unwrap_or_else(|| String::from("2015")))
+    }
+
+    /// Metadata of the package in this target's directory.
+    fn package_metadata(&self) -> anyhow::Result<(Arc<Metadata>, MetadataPackage)> {
+        let manifest_path = self.path.join("Cargo.toml").canonicalize()?;
+
+        // A package missing from cached metadata may be a member added since.
+        for refresh in [false, true] {
+            let metadata = workspace_metadata(self.root(), refresh)?;
+            let package = metadata
+                .packages
+                .iter()
+                .find(|p| p.manifest_path == manifest_path)
+                .cloned();
+            if let Some(package) = package {
+                return Ok((metadata, package));
+            }
+        }
+
+        anyhow::bail!("No package in metadata for {manifest_path:?}")
+    }
+
+    /// Targets for the packages this target depends on by path.
+    ///
+    /// Development dependencies are only included with `include_dev`, since they are allowed to
+    /// form cycles.
+    fn path_dependencies(&self, include_dev: bool) -> anyhow::Result<Vec<RustCargoTarget>> {
+        if let Kind::Workspace { .. } = self.kind {
+            let members = self.members()?;
+            let member_addresses = members
+                .iter()
+                .map(|m| m.to_string())
+                .collect::<HashSet<_>>();
+
+            let mut result = Vec::new();
+            for member in members {
+                result.extend(
+                    member
+                        .path_dependencies(include_dev)?
+                        .into_iter()
+                        .filter(|d| !member_addresses.contains(&d.to_string())),
+                );
+            }
+            return Ok(result);
+        }
+
+        let (_, package) = self.package_metadata()?;
+        package
+            .dependencies
+            .into_iter()
+            .filter(|d| include_dev || d.kind.as_deref() != Some("dev"))
+            .filter_map(|d| d.path)
+            // Packages outside of the current directory aren't targets.
+            .filter_map(|path| relative_to_current_dir(&path).ok())
+            .map(|path| RustCargoTarget::package(&path))
+            .collect()
+    }
+
+    fn collect_src_files(
+        &self,
+        result: &mut FileSelector,
+        visited: &mut HashSet<PathBuf>,
+    ) -> anyhow::Result<()> {
+        if !visited.insert(self.path.clone()) {
+            return Ok(());
+        }
+
+        result.include(
+            FileSelector::builder()
+                .set_subdir(self.root())
+                .path("Cargo.toml")
+                .path("Cargo.lock")
+                .build(),
+        );
+
+        if let Kind::Crate | Kind::Member { .. } = self.kind {
+            let (metadata, package) = self.package_metadata()?;
+            let package_dir = simplify(&self.path)?;
+
+            let mut builder = FileSelector::builder().path(self.path.join("Cargo.toml"));
+            for target in &package.targets {
+                let src_path = simplify(relative_to_current_dir(&target.src_path)?)?;
+                let src_dir = src_path.parent().expect("file has parent");
+
+                // Files like `build.rs` are alone in the package directory, otherwise modules
+                // live alongside the target's root file.
+                builder = if src_dir == package_dir {
+                    builder.path(&src_path)
+                } else {
+                    let escaped = glob::Pattern::escape(&src_dir.display().to_string());
+                    builder.glob(&format!("{escaped}/**/*.rs"))?
+                };
+            }
+
+            // Files the compiler read in previous builds, including `include_str!` and friends.
+            for path in dep_info_files(&metadata, &package)? {
+                builder = builder.path(path);
+            }
+
+            result.include(builder.build());
+        }
+
+        for target in self
+            .members()?
+            .into_iter()
+            .chain(self.path_dependencies(true)?)
+        {
+            target.collect_src_files(result, visited)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Modification times of the manifests metadata was read from, along with the metadata.
+type CachedMetadata = (Vec<(PathBuf, SystemTime)>, Arc<Metadata>);
+
+/// `cargo metadata` output by workspace root.
+static METADATA: Mutex<BTreeMap<PathBuf, CachedMetadata>> = Mutex::new(BTreeMap::new());
+
+/// Metadata of the workspace, or package outside of a workspace, at `root`.
+///
+/// Each workspace is only read once, since every member and dependent reads the metadata of
+/// its packages, unless one of its manifests changed or `refresh` is set.
+fn workspace_metadata(root: &Path, refresh: bool) -> anyhow::Result<Arc<Metadata>> {
+    let root = root.canonicalize().context(format!("Finding {root:?}"))?;
+    let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+
+    if !refresh {
+        if let Some((manifests, metadata)) = METADATA.lock().unwrap().get(&root) {
+            if manifests
+                .iter()
+                .all(|(path, time)| modified(path) == Some(*time))
+            {
+                return Ok(Arc::clone(metadata));
+            }
+        }
+    }
+
+    let output = Command::new("cargo")
+        .args([
+            "metadata",
+            "--format-version=1",
+            "--no-deps",
+            "--manifest-path",
+        ])
+        .arg(root.join("Cargo.toml"))
+        .output()
+        .context("Running cargo metadata")?
+        .success_ok()
+        .map_err(|out| anyhow::anyhow!("{}", out.stderr))?;
+    let metadata: Arc<Metadata> = Arc::new(serde_json::from_str(&output.stdout)?);
+
+    let manifests = std::iter::once(root.join("Cargo.toml"))
+        .chain(metadata.packages.iter().map(|p| p.manifest_path.clone()))
+        .filter_map(|path| Some((path.clone(), modified(&path)?)))
+        .collect();
+    METADATA
+        .lock()
+        .unwrap()
+        .insert(root, (manifests, Arc::clone(&metadata)));
+
+    Ok(metadata)
+}
+
+/// Source files listed in the compiler's dep-info files for targets of `package`.
+fn dep_info_files(metadata: &Metadata, package: &MetadataPackage) -> anyhow::Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+
+    for target in &package.targets {
+        let crate_name = target.name.replace('-', "_");
+        let pattern = format!(
+            "{}/*/deps/{}-*.d",
+            glob::Pattern::escape(&metadata.target_directory.display().to_string()),
+            glob::Pattern::escape(&crate_name),
+        );
+
+        for dep_info in glob::glob(&pattern)? {
+            let contents = std::fs::read_to_string(dep_info?)?;
+            for path in parse_dep_info(&contents) {
+                let path = metadata.workspace_root.join(path);
+                if path.starts_with(&metadata.target_directory) {
+                    continue;
+                }
+                if let Ok(path) = relative_to_current_dir(&path) {
+                    result.push(path);
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Paths in a makefile-style dep-info file.
+fn parse_dep_info(contents: &str) -> BTreeSet<PathBuf> {
+    let mut result = BTreeSet::new();
+
+    for line in contents.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let mut current = String::new();
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => current.extend(chars.next()),
+                ' ' => {
+                    result.extend(dep_info_path(&current));
+                    current.clear();
+                }
+                c => current.push(c),
+            }
+        }
+        result.extend(dep_info_path(&current));
+    }
+
+    result
+}
+
+fn dep_info_path(token: &str) -> Option<PathBuf> {
+    let token = token.strip_suffix(':').unwrap_or(token);
+    (!token.is_empty()).then(|| PathBuf::from(token))
+}
+
+/// Directory of the workspace containing the package at `path`, if any.
+fn find_workspace(path: &Path, package: &Package) -> anyhow::Result<Option<PathBuf>> {
+    if let Some(workspace) = &package.workspace {
+        return Ok(Some(simplify(path.join(workspace))?));
+    }
+
+    for ancestor in path.ancestors().skip(1) {
+        if !ancestor.join("Cargo.toml").try_exists()? {
+            continue;
+        }
+
+        // Cargo uses the first ancestor with a workspace, even when it does not include the
+        // package.
+        if let Some(workspace) = CargoTomlFile::read(ancestor)?.workspace {
+            let is_member = workspace.member_dirs(ancestor)?.contains(&simplify(path)?);
+            return Ok(is_member.then(|| ancestor.to_path_buf()));
+        }
     }
+
+    Ok(None)
 }
 
 impl Display for RustCargoTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO(shelbyd): De-duplicate formatting of target addresses.
-        let package = self.path.display().to_string().replacen("./", "", 1);
-        write!(f, "//{package}:rust_crate")
+        fmt_address(f, &self.path, self.kind())
     }
 }
 
 impl Target for RustCargoTarget {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            Kind::Crate | Kind::Member { .. } => "rust_crate",
+            Kind::Workspace { .. } => "rust_workspace",
+        }
+    }
+
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
     fn perform_test(&self) -> anyhow::Result<()> {
-        Command::new("cargo")
-            .args(&[
-                "test",
-                "--manifest-path",
-                &self.path.join("Cargo.toml").to_string_lossy(),
-                "--color=always",
-            ])
+        self.cargo("test")
+            .arg("--color=always")
             .output()?
             .success_ok()
             .map(|_| ())
             .map_err(|out| anyhow::anyhow!("{}\n{}", out.stderr, out.stdout))
     }
 
-    fn perform_lint(&self) -> anyhow::Result<()> {
-        Command::new("cargo")
-            .args([
-                "clippy",
-                "--manifest-path",
-                &self.path.join("Cargo.toml").to_string_lossy(),
-                "--no-deps",
-                "--color=always",
-                "--",
-                "--deny=warnings",
-            ])
+    fn perform_lint(&self, mode: Mode) -> anyhow::Result<()> {
+        self.cargo("clippy")
+            .args(clippy_args(mode))
             .output()?
             .success_ok()
             .map(|_| ())
             .map_err(|out| anyhow::anyhow!("{}\n{}", out.stderr, out.stdout))
     }
 
-    fn perform_format(&self) -> anyhow::Result<()> {
-        Command::new("cargo")
-            .args([
-                "fmt",
-                "--manifest-path",
-                &self.path.join("Cargo.toml").to_string_lossy(),
-                "--check",
-            ])
-            .output()?
-            .success_ok()
-            .map(|_| ())
-            .map_err(|out| anyhow::anyhow!("{}\n{}", out.stderr, out.stdout))
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()> {
+        let mut command = self.cargo("fmt");
+
+        if mode == Mode::Fix {
+            return command
+                .output()?
+                .success_ok()
+                .map(|_| ())
+                .map_err(|out| anyhow::anyhow!("{}\n{}", out.stderr, out.stdout));
+        }
+
+        let out = command
+            .args(["--check", "--message-format", "short"])
+            .output()?;
+        let out = match out.success_ok() {
+            Ok(_) => return Ok(()),
+            Err(out) => out,
+        };
+
+        // Errors, like files that don't parse, leave out the list of files to diff. Warnings,
+        // like unstable options in rustfmt.toml, are shown with the diff.
+        let unformatted = out.stdout.lines().map(Path::new).collect::<Vec<_>>();
+        if unformatted.is_empty() || !unformatted.iter().all(|path| path.is_file()) {
+            anyhow::bail!("{}\n{}", out.stderr, out.stdout);
+        }
+
+        let edition = self.edition()?;
+        let mut diff = String::new();
+        for path in unformatted {
+            let original = std::fs::read_to_string(path)?;
+            let formatted = rustfmt(path, &original, &edition)?;
+            diff.push_str(&unified_diff(path, &original, &formatted));
+        }
+
+        anyhow::bail!("{}cargo fmt found unformatted files:\n{diff}", out.stderr)
     }
 
     fn perform_build(&self, build: &Build) -> anyhow::Result<()> {
         use std::os::unix::fs::PermissionsExt;
 
-        Command::new("cargo")
-            .args(&[
-                "build",
-                "--release",
-                "--manifest-path",
-                &self.path.join("Cargo.toml").to_string_lossy(),
-                "--color=always",
-            ])
+        self.cargo("build")
+            .args(["--release", "--color=always"])
             .output()?
             .success_ok()
             .map_err(|out| anyhow::anyhow!("{}\n{}", out.stderr, out.stdout))?;
 
-        let release_dir = self.path.join("target/release");
+        let release_dir = self.root().join("target/release");
+
+        // Members share the workspace's target directory with the other members.
+        if let Kind::Member { .. } = self.kind {
+            let (_, package) = self.package_metadata()?;
+            for target in package.targets.iter().filter(|t| t.is_bin()) {
+                let path = release_dir.join(&target.name);
+                std::fs::copy(&path, build.out.join(&target.name))
+                    .context(format!("Copying {path:?}"))?;
+            }
+            return Ok(());
+        }
+
         for entry in std::fs::read_dir(&release_dir)
             .context(format!("Listing contents of {release_dir:?}"))?
         {
@@ -115,68 +488,286 @@ impl Target for RustCargoTarget {
     }
 
     fn cache_paths(&self) -> HashSet<PathBuf> {
-        [self.path.join("target")].into_iter().collect()
+        [self.root().join("target")].into_iter().collect()
     }
 
     fn lock_files(&self) -> HashSet<PathBuf> {
-        [self.path.join("Cargo.lock")]
+        [self.root().join("Cargo.lock")]
             .into_iter()
             .filter(|p| p.exists())
             .collect()
     }
 
     fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
-        let direct = FileSelector::builder()
-            .set_subdir(&self.path)
-            .path("Cargo.toml")
-            .path("Cargo.lock")
-            .glob("src/**/*.rs")
-            .unwrap()
-            .glob("test/**/*.rs")
-            .unwrap()
-            .build();
-
-        let cargo_toml_contents = std::fs::read_to_string(self.path.join("Cargo.toml"))?;
-        let cargo_toml: CargoTomlFile = toml::de::from_str(&cargo_toml_contents)?;
-
-        let paths = cargo_toml
-            .dependencies
-            .into_values()
-            .filter_map(|d| match d {
-                Dependency::Pathed { path } => Some(path),
-                _ => None,
-            });
+        let mut result = FileSelector::builder().build();
+        self.collect_src_files(&mut result, &mut HashSet::new())?;
+        Ok(Some(result))
+    }
 
-        let mut result = direct;
-        for path in paths {
-            let target = RustCargoTarget::new(&self.path.join(&path));
-            let target_srcs = target
-                .src_files()?
-                .expect("implemented for RustCargoTarget");
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        let mut inputs = vec![
+            tool_version("rustc", &["-vV"])?,
+            tool_version("cargo", &["-V"])?,
+        ];
+        inputs.extend(env_inputs(&["RUSTFLAGS", "CARGO_BUILD_TARGET"]));
+        Ok(inputs)
+    }
 
-            result.include(target_srcs);
-        }
+    fn dependencies(&self) -> anyhow::Result<HashSet<String>> {
+        Ok(self
+            .path_dependencies(false)?
+            .into_iter()
+            .map(|t| t.to_string())
+            .collect())
+    }
 
-        Ok(Some(result))
+    fn members(&self) -> anyhow::Result<HashSet<String>> {
+        Ok(RustCargoTarget::members(self)?
+            .into_iter()
+            .map(|t| t.to_string())
+            .collect())
     }
 }
 
+/// Formats `contents` of the file at `path` as rustfmt would, respecting its rustfmt.toml.
+fn rustfmt(path: &Path, contents: &str, edition: &str) -> anyhow::Result<String> {
+    use std::io::Write;
+
+    let mut child = Command::new("rustfmt")
+        .args(["--emit", "stdout", "--edition", edition])
+        .current_dir(path.parent().expect("file has parent"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Running rustfmt")?;
+    child
+        .stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(contents.as_bytes())?;
+
+    child
+        .wait_with_output()?
+        .success_ok()
+        .map(|out| out.stdout)
+        .map_err(|out| anyhow::anyhow!("{}", out.stderr))
+}
+
 #[derive(Deserialize, Debug)]
 struct CargoTomlFile {
-    dependencies: BTreeMap<String, Dependency>,
+    package: Option<Package>,
+    workspace: Option<Workspace>,
+}
+
+impl CargoTomlFile {
+    fn read(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join("Cargo.toml");
+        let contents = std::fs::read_to_string(&path).context(format!("Reading {path:?}"))?;
+        toml::de::from_str(&contents).context(format!("Parsing {path:?}"))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Package {
+    name: String,
+    /// A table when inherited from the workspace.
+    edition: Option<toml::Value>,
+    /// Path to the workspace root, when not an ancestor directory.
+    workspace: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Workspace {
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<PathBuf>,
+    package: Option<WorkspacePackage>,
+}
+
+impl Workspace {
+    /// Directories of the members of the workspace at `root`.
+    fn member_dirs(&self, root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let excluded = self
+            .exclude
+            .iter()
+            .map(|e| simplify(root.join(e)))
+            .collect::<anyhow::Result<HashSet<_>>>()?;
+
+        let mut result = BTreeSet::new();
+        for member in &self.members {
+            let pattern = root.join(member).display().to_string();
+            for path in glob::glob(&pattern)? {
+                let path = simplify(path?)?;
+                if path.join("Cargo.toml").try_exists()? && !excluded.contains(&path) {
+                    result.insert(path);
+                }
+            }
+        }
+
+        // The root package is always a member.
+        let root = simplify(root)?;
+        if CargoTomlFile::read(&root)?.package.is_some() {
+            result.insert(root);
+        }
+
+        Ok(result.into_iter().collect())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct WorkspacePackage {
+    edition: Option<String>,
 }
 
+/// Output of `cargo metadata`.
 #[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum Dependency {
-    Pathed {
-        path: PathBuf,
-    },
-
-    // These are so that serde can parse different dependencies.
-    JustVersion(String),
-    #[allow(dead_code)]
-    Versioned {
-        version: String,
-    },
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+    target_directory: PathBuf,
+    workspace_root: PathBuf,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct MetadataPackage {
+    manifest_path: PathBuf,
+    targets: Vec<MetadataTarget>,
+    dependencies: Vec<MetadataDependency>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct MetadataTarget {
+    name: String,
+    kind: Vec<String>,
+    src_path: PathBuf,
+}
+
+impl MetadataTarget {
+    fn is_bin(&self) -> bool {
+        self.kind.iter().any(|k| k == "bin")
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct MetadataDependency {
+    /// Only set for path dependencies.
+    path: Option<PathBuf>,
+    /// `None` for normal dependencies, otherwise `dev` or `build`.
+    kind: Option<String>,
+}
+
+/// Arguments to `cargo clippy`: check denies warnings, fix applies machine-applicable suggestions.
+fn clippy_args(mode: Mode) -> &'static [&'static str] {
+    match mode {
+        Mode::Check => &["--no-deps", "--color=always", "--", "--deny=warnings"],
+        Mode::Fix => &[
+            "--no-deps",
+            "--color=always",
+            "--fix",
+            "--allow-dirty",
+            "--allow-staged",
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn package(name: &str) -> String {
+        format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n")
+    }
+
+    #[test]
+    fn workspace_members() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            &root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/excluded\"]\n",
+        );
+        write(&root.join("crates/a/Cargo.toml"), &package("a"));
+        write(&root.join("crates/b/Cargo.toml"), &package("b"));
+        write(
+            &root.join("crates/excluded/Cargo.toml"),
+            &package("excluded"),
+        );
+
+        let targets = discover(root, &Config::default()).unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].kind(), "rust_workspace");
+
+        let members = targets[0].members().unwrap();
+        assert_eq!(
+            members,
+            [
+                format!("//{}/crates/a:rust_crate", root.display()),
+                format!("//{}/crates/b:rust_crate", root.display()),
+            ]
+            .into()
+        );
+
+        let member = RustCargoTarget::package(&root.join("crates/a")).unwrap();
+        assert!(matches!(member.kind, Kind::Member { ref name, .. } if name == "a"));
+
+        let excluded = RustCargoTarget::package(&root.join("crates/excluded")).unwrap();
+        assert!(matches!(excluded.kind, Kind::Crate));
+    }
+
+    #[test]
+    fn parses_dep_info() {
+        let contents = "\
+/target/debug/deps/a-123.d: src/lib.rs src/with\\ space.rs data.txt
+
+src/lib.rs:
+src/with\\ space.rs:
+data.txt:
+
+# env-dep:CARGO_PKG_NAME=a
+";
+
+        assert_eq!(
+            parse_dep_info(contents),
+            [
+                "/target/debug/deps/a-123.d",
+                "src/lib.rs",
+                "src/with space.rs",
+                "data.txt"
+            ]
+            .map(PathBuf::from)
+            .into()
+        );
+    }
+
+    #[test]
+    fn root_package_is_member() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            &root.join("Cargo.toml"),
+            &format!("{}[workspace]\n", package("root")),
+        );
+
+        let targets = discover(root, &Config::default()).unwrap();
+        let kinds = targets.iter().map(|t| t.kind()).collect::<Vec<_>>();
+        assert_eq!(kinds, vec!["rust_workspace", "rust_crate"]);
+        assert_eq!(
+            targets[0].members().unwrap(),
+            [targets[1].to_string()].into()
+        );
+    }
+
+    #[test]
+    fn clippy_fixes_only_in_fix_mode() {
+        assert!(clippy_args(Mode::Check).ends_with(&["--", "--deny=warnings"]));
+        assert!(!clippy_args(Mode::Check).contains(&"--fix"));
+        assert!(clippy_args(Mode::Fix).contains(&"--fix"));
+        assert!(!clippy_args(Mode::Fix).contains(&"--deny=warnings"));
+    }
 }
@@ -0,0 +1,191 @@
+use super::*;
+
+use anyhow::Context;
+use std::io::{BufRead, BufReader, Read};
+
+/// Shells that shellcheck and shfmt understand.
+const SHELLS: [&str; 5] = ["sh", "bash", "dash", "ksh", "mksh"];
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+fn discover(path: &Path, _: &Config) -> anyhow::Result<Targets> {
+    let mut scripts = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && is_shell_script(&entry.path()) {
+            scripts.push(entry.path());
+        }
+    }
+
+    if scripts.is_empty() {
+        return Ok(Vec::new());
+    }
+    scripts.sort();
+
+    Ok(vec![Box::new(ShellScriptsTarget {
+        path: path.into(),
+        scripts,
+    })])
+}
+
+/// Whether `path` has a shell script extension, or is an executable or extensionless file with
+/// a shell shebang. Files that can't be read aren't scripts.
+fn is_shell_script(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match path.extension() {
+        Some(ext) if ext == "sh" || ext == "bash" => return true,
+        Some(_) => {
+            let executable = path
+                .metadata()
+                .is_ok_and(|m| m.permissions().mode() & 0o111 != 0);
+            if !executable {
+                return false;
+            }
+        }
+        None => {}
+    }
+
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let mut first_line = Vec::new();
+    if BufReader::new(file.take(256))
+        .read_until(b'\n', &mut first_line)
+        .is_err()
+    {
+        return false;
+    }
+
+    shebang_shell(&String::from_utf8_lossy(&first_line)).is_some()
+}
+
+/// The shell a shebang line runs, like `bash` for `#!/usr/bin/env bash`.
+fn shebang_shell(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+
+    SHELLS.contains(&program).then_some(program)
+}
+
+/// The shell scripts directly in a directory.
+pub struct ShellScriptsTarget {
+    path: PathBuf,
+    scripts: Vec<PathBuf>,
+}
+
+impl Display for ShellScriptsTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_address(f, &self.path, self.kind())
+    }
+}
+
+impl Target for ShellScriptsTarget {
+    fn kind(&self) -> &'static str {
+        "shell_scripts"
+    }
+
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn perform_lint(&self, _: Mode) -> anyhow::Result<()> {
+        // shellcheck has no fixes to apply.
+        Command::new("shellcheck")
+            .args(&self.scripts)
+            .output()
+            .context("Running shellcheck")?
+            .success_ok()
+            .map(|_| ())
+            .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
+    }
+
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()> {
+        let flag = match mode {
+            Mode::Check => "-d",
+            Mode::Fix => "-w",
+        };
+
+        Command::new("shfmt")
+            .arg(flag)
+            .args(&self.scripts)
+            .output()
+            .context("Running shfmt")?
+            .success_ok()
+            .map(|_| ())
+            .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
+    }
+
+    fn perform_build(&self, _: &Build) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        // Either tool may be missing when only the other's action runs, in which case that
+        // action fails and isn't cached.
+        Ok([("shellcheck", "--version"), ("shfmt", "--version")]
+            .into_iter()
+            .filter_map(|(program, arg)| tool_version(program, &[arg]).ok())
+            .collect())
+    }
+
+    fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
+        let builder = self
+            .scripts
+            .iter()
+            .fold(FileSelector::builder(), |builder, script| {
+                builder.path(script)
+            });
+        Ok(Some(builder.build()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebangs() {
+        assert_eq!(shebang_shell("#!/bin/sh\n"), Some("sh"));
+        assert_eq!(shebang_shell("#! /usr/bin/env bash\n"), Some("bash"));
+        assert_eq!(shebang_shell("#!/usr/bin/env -S bash -e\n"), Some("bash"));
+        assert_eq!(shebang_shell("#!/usr/bin/env python3\n"), None);
+        assert_eq!(shebang_shell("#!/bin/zsh\n"), None);
+        assert_eq!(shebang_shell("echo hi\n"), None);
+    }
+
+    #[test]
+    fn detects_scripts() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, contents: &str, mode: u32| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            path
+        };
+
+        assert!(is_shell_script(&file("build.sh", "", 0o644)));
+        assert!(is_shell_script(&file("gtl", "#!/bin/sh\n", 0o644)));
+        assert!(is_shell_script(&file(
+            "run.command",
+            "#!/bin/bash\n",
+            0o755
+        )));
+        assert!(!is_shell_script(&file("notes.txt", "#!/bin/sh\n", 0o644)));
+        assert!(!is_shell_script(&file(
+            "tool",
+            "#!/usr/bin/env python3\n",
+            0o755
+        )));
+        assert!(!is_shell_script(&dir.path().join("missing")));
+    }
+}
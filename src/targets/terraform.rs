@@ -0,0 +1,277 @@
+use super::*;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+fn discover(path: &Path, config: &Config) -> anyhow::Result<Targets> {
+    if !has_tf_files(path)? {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Box::new(TerraformModuleTarget {
+        path: path.into(),
+        program: config.terraform.program.clone(),
+    })])
+}
+
+fn has_tf_files(dir: &Path) -> anyhow::Result<bool> {
+    for entry in std::fs::read_dir(dir).context(format!("Reading {dir:?}"))? {
+        let entry = entry?;
+        if entry.path().extension().is_some_and(|ext| ext == "tf") && entry.file_type()?.is_file() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Options for Terraform targets, from the `[terraform]` section of `gentle.toml`.
+#[derive(Deserialize, Default, Debug)]
+pub struct TerraformConfig {
+    /// Program to run, like `tofu`, detected from those installed by default.
+    program: Option<String>,
+}
+
+/// A Terraform or OpenTofu module, the `.tf` files directly in a directory.
+pub struct TerraformModuleTarget {
+    path: PathBuf,
+    program: Option<String>,
+}
+
+impl TerraformModuleTarget {
+    /// The first of `terraform` or `tofu` installed, unless configured.
+    fn program(&self) -> anyhow::Result<&str> {
+        if let Some(program) = &self.program {
+            return Ok(program);
+        }
+        ["terraform", "tofu"]
+            .into_iter()
+            .find(|p| on_path(p))
+            .ok_or_else(|| anyhow::anyhow!("Found neither terraform nor tofu on PATH"))
+    }
+
+    fn command(&self, args: &[&str]) -> anyhow::Result<Command> {
+        let mut command = Command::new(self.program()?);
+        command.args(args).current_dir(&self.path);
+        Ok(command)
+    }
+
+    fn lock_file(&self) -> PathBuf {
+        self.path.join(".terraform.lock.hcl")
+    }
+
+    /// Directories of modules this one uses from local paths.
+    fn local_modules(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut result = Vec::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "tf") {
+                continue;
+            }
+
+            let contents = std::fs::read_to_string(&path).context(format!("Reading {path:?}"))?;
+            for source in local_module_sources(&contents) {
+                let dir = self.path.join(source);
+                let Ok(dir) = relative_to_current_dir(&dir.canonicalize().unwrap_or(dir)) else {
+                    continue;
+                };
+                if has_tf_files(&dir).unwrap_or(false)
+                    && !result
+                        .iter()
+                        .any(|d| simplify(d).ok() == simplify(&dir).ok())
+                {
+                    result.push(dir);
+                }
+            }
+        }
+        result.sort();
+        Ok(result)
+    }
+
+    fn collect_src_files(
+        &self,
+        result: &mut FileSelector,
+        visited: &mut HashSet<PathBuf>,
+    ) -> anyhow::Result<()> {
+        if !visited.insert(simplify(&self.path)?) {
+            return Ok(());
+        }
+
+        result.include(
+            FileSelector::builder()
+                .set_subdir(&self.path)
+                .glob("*.tf")?
+                .glob("*.tf.json")?
+                .glob("*.tfvars")?
+                .path(".terraform.lock.hcl")
+                .path(".tflint.hcl")
+                .build(),
+        );
+
+        for dir in self.local_modules()? {
+            let module = TerraformModuleTarget {
+                path: dir,
+                program: self.program.clone(),
+            };
+            module.collect_src_files(result, visited)?;
+        }
+        Ok(())
+    }
+}
+
+fn run(command: &mut Command) -> anyhow::Result<()> {
+    let program = command.get_program().to_string_lossy().to_string();
+    command
+        .output()
+        .context(format!("Running {program}"))?
+        .success_ok()
+        .map(|_| ())
+        .map_err(|out| anyhow::anyhow!("{}{}", out.stdout, out.stderr))
+}
+
+/// Local paths in `source` arguments of `module` blocks, like `../modules/network`.
+fn local_module_sources(contents: &str) -> Vec<&str> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let value = line.trim().strip_prefix("source")?.trim_start();
+            let value = value.strip_prefix('=')?.trim();
+            let value = value.strip_prefix('"')?.split('"').next()?;
+            (value.starts_with("./") || value.starts_with("../")).then_some(value)
+        })
+        .collect()
+}
+
+impl Display for TerraformModuleTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_address(f, &self.path, self.kind())
+    }
+}
+
+impl Target for TerraformModuleTarget {
+    fn kind(&self) -> &'static str {
+        "terraform_module"
+    }
+
+    fn dir(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn perform_lint(&self, mode: Mode) -> anyhow::Result<()> {
+        // Installs providers and modules without configuring state, which validate needs. Only
+        // fixing creates the lock file, checking fails for providers missing from it.
+        let has_lock_file = self.lock_file().try_exists()?;
+        let mut init = self.command(&["init", "-backend=false", "-input=false"])?;
+        if has_lock_file || mode == Mode::Check {
+            init.arg("-lockfile=readonly");
+        }
+        let init = run(&mut init);
+        if has_lock_file || mode == Mode::Fix {
+            init?;
+        } else {
+            init.context("Missing .terraform.lock.hcl, `./gtl lint --fix` creates it")?;
+        }
+        run(&mut self.command(&["validate", "-no-color"])?)?;
+
+        if !on_path("tflint") {
+            return Ok(());
+        }
+        let tflint = || {
+            let mut command = Command::new("tflint");
+            command.current_dir(&self.path);
+            command
+        };
+        if self.path.join(".tflint.hcl").try_exists()? {
+            run(tflint().arg("--init"))?;
+        }
+        let mut lint = tflint();
+        if mode == Mode::Fix {
+            lint.arg("--fix");
+        }
+        run(&mut lint)
+    }
+
+    fn perform_format(&self, mode: Mode) -> anyhow::Result<()> {
+        let args: &[&str] = match mode {
+            Mode::Check => &["fmt", "-check", "-diff", "-no-color"],
+            Mode::Fix => &["fmt"],
+        };
+        run(&mut self.command(args)?)
+    }
+
+    fn perform_build(&self, _: &Build) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn cache_key_inputs(&self) -> anyhow::Result<Vec<String>> {
+        let mut inputs = vec![tool_version(self.program()?, &["version"])?];
+        // tflint is optional, so only its version is included when it's installed.
+        inputs.extend(tool_version("tflint", &["--version"]).ok());
+        inputs.extend(env_inputs(&["TF_CLI_ARGS", "TF_CLI_CONFIG_FILE"]));
+        Ok(inputs)
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        let mut result: HashSet<_> = [self.path.join(".terraform")].into_iter().collect();
+        result.extend(std::env::var_os("TF_PLUGIN_CACHE_DIR").map(PathBuf::from));
+        result
+    }
+
+    fn lock_files(&self) -> HashSet<PathBuf> {
+        [self.lock_file()]
+            .into_iter()
+            .filter(|f| f.exists())
+            .collect()
+    }
+
+    fn src_files(&self) -> anyhow::Result<Option<FileSelector>> {
+        let mut result = FileSelector::builder().build();
+        self.collect_src_files(&mut result, &mut HashSet::new())?;
+        Ok(Some(result))
+    }
+
+    fn dependencies(&self) -> anyhow::Result<HashSet<String>> {
+        Ok(self
+            .local_modules()?
+            .into_iter()
+            .map(|dir| {
+                TerraformModuleTarget {
+                    path: dir,
+                    program: None,
+                }
+                .to_string()
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_local_module_sources() {
+        let contents = r#"
+module "network" {
+  source = "../modules/network"
+}
+
+module "vpc" {
+  source  = "terraform-aws-modules/vpc/aws"
+  version = "5.0.0"
+}
+
+module "local" {
+  source="./local" # comment
+}
+"#;
+        assert_eq!(
+            local_module_sources(contents),
+            ["../modules/network", "./local"]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Polls the repository for changed files.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+type Snapshot = HashMap<PathBuf, (SystemTime, u64)>;
+
+pub struct Watcher {
+    snapshot: Snapshot,
+}
+
+impl Watcher {
+    /// Watch files with the same ignore rules as target discovery, except those Gentle writes.
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Watcher {
+            snapshot: snapshot()?,
+        })
+    }
+
+    /// Block until files change, returning every path that changed.
+    ///
+    /// Waits for changes to settle so a burst of saves is reported together.
+    pub fn wait_for_changes(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut changed = BTreeSet::new();
+
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+
+            let next = snapshot()?;
+            let new_changes = changed_paths(&self.snapshot, &next);
+            self.snapshot = next;
+
+            if new_changes.is_empty() && !changed.is_empty() {
+                return Ok(changed.into_iter().collect());
+            }
+            changed.extend(new_changes);
+        }
+    }
+}
+
+fn snapshot() -> anyhow::Result<Snapshot> {
+    let mut result = HashMap::new();
+
+    for entry in ignore::Walk::new("./") {
+        let entry = entry?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+
+        let path = crate::file_selector::simplify(entry.path())?;
+        if crate::file_selector::is_generated(&path) {
+            continue;
+        }
+
+        // Files can be removed while walking, they will show as removed in the next snapshot.
+        let metadata = match entry.metadata() {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+        result.insert(path, (metadata.modified()?, metadata.len()));
+    }
+
+    Ok(result)
+}
+
+fn changed_paths(before: &Snapshot, after: &Snapshot) -> Vec<PathBuf> {
+    let modified = after
+        .iter()
+        .filter(|(path, stat)| before.get(*path) != Some(stat))
+        .map(|(path, _)| path.clone());
+    let removed = before
+        .keys()
+        .filter(|path| !after.contains_key(*path))
+        .cloned();
+
+    modified.chain(removed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(entries: &[(&str, u64)]) -> Snapshot {
+        entries
+            .iter()
+            .map(|(path, len)| (PathBuf::from(path), (SystemTime::UNIX_EPOCH, *len)))
+            .collect()
+    }
+
+    #[test]
+    fn detects_added_modified_and_removed() {
+        let before = snapshot(&[("same", 1), ("modified", 1), ("removed", 1)]);
+        let after = snapshot(&[("same", 1), ("modified", 2), ("added", 1)]);
+
+        let mut changed = changed_paths(&before, &after);
+        changed.sort();
+
+        assert_eq!(
+            changed,
+            ["added", "modified", "removed"].map(PathBuf::from).to_vec()
+        );
+    }
+}